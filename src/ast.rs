@@ -0,0 +1,958 @@
+use core::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    lexer::{is_word_char, SPECIAL_CHARS},
+    span::{Location, Span, Spanned},
+};
+
+pub type Program = Vec<Statement>;
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    Map(Map),
+    Unmap(Unmap),
+    Set(Set),
+    Cmd(Cmd),
+    Source(Source),
+    If(If),
+    Let(Let),
+}
+
+impl Statement {
+    /// Returns the inner `Map` if this statement is a `map` statement.
+    pub fn as_map(&self) -> Option<&Map> {
+        match self {
+            Statement::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner `Unmap` if this statement is an `unmap` statement.
+    pub fn as_unmap(&self) -> Option<&Unmap> {
+        match self {
+            Statement::Unmap(unmap) => Some(unmap),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner `Set` if this statement is a `set` statement.
+    pub fn as_set(&self) -> Option<&Set> {
+        match self {
+            Statement::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner `Cmd` if this statement is a `cmd` statement.
+    pub fn as_cmd(&self) -> Option<&Cmd> {
+        match self {
+            Statement::Cmd(cmd) => Some(cmd),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner `If` if this statement is an `if` block.
+    pub fn as_if(&self) -> Option<&If> {
+        match self {
+            Statement::If(if_block) => Some(if_block),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner `Let` if this statement is a `let` statement.
+    pub fn as_let(&self) -> Option<&Let> {
+        match self {
+            Statement::Let(let_statement) => Some(let_statement),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner `Source` if this statement is a `source` statement.
+    pub fn as_source(&self) -> Option<&Source> {
+        match self {
+            Statement::Source(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Spanned for Statement {
+    fn span(&self) -> Span {
+        match self {
+            Statement::Map(map) => map.span(),
+            Statement::Unmap(unmap) => unmap.span(),
+            Statement::Set(set) => set.span(),
+            Statement::Cmd(cmd) => cmd.span(),
+            Statement::Source(source) => source.span(),
+            Statement::If(if_block) => if_block.span(),
+            Statement::Let(let_statement) => let_statement.span(),
+        }
+    }
+}
+
+/// A `map` statement, binding a sequence of keys to a command.
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub(crate) mode: Mode,
+    pub(crate) keys: Vec<Key>,
+    pub(crate) command: Command,
+    pub(crate) span: Span,
+}
+
+impl Map {
+    /// The mode that the binding applies in, as in `map[visual] d delete`. Normal mode unless
+    /// the statement names another one.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The sequence of key chords that triggers this mapping. Never empty.
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// The command that the key is bound to.
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
+
+    /// The name of the command that the key is bound to.
+    pub fn cmd_name(&self) -> &str {
+        self.command.name()
+    }
+}
+
+impl Spanned for Map {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// An `unmap` statement, removing a binding made earlier, e.g. by the host's defaults.
+#[derive(Debug, Clone)]
+pub struct Unmap {
+    pub(crate) mode: Mode,
+    pub(crate) keys: Vec<Key>,
+    pub(crate) is_prefix: bool,
+    pub(crate) span: Span,
+}
+
+impl Unmap {
+    /// The mode to remove the binding from, as in `unmap[visual] d`.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The sequence of keys to unbind. Only empty for `unmap *`.
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// Returns true for the `unmap <keys> *` form, which removes every binding that starts with
+    /// the keys rather than only the binding for the keys themselves.
+    pub fn is_prefix(&self) -> bool {
+        self.is_prefix
+    }
+}
+
+impl Spanned for Unmap {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A `set` statement, giving an option a value.
+///
+/// `set <name>` sets a boolean option to true, and `set no<name>` sets it to false. An option's
+/// own name can start with `no` too, as with `notify`, so the name as written is kept for
+/// [`OptionSchema`](crate::OptionSchema), which reads `set notify` as turning on `notify` when
+/// there's an option called that.
+#[derive(Debug, Clone)]
+pub struct Set {
+    pub(crate) name: String,
+    pub(crate) name_span: Span,
+    pub(crate) value: OptionValue,
+    pub(crate) value_span: Span,
+    /// For `set no<name>`, the name as written, including the `no`.
+    pub(crate) negated_from: Option<String>,
+    pub(crate) span: Span,
+}
+
+impl Set {
+    /// The name of the option. For `set no<name>`, this is `<name>`, without the `no`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// For `set no<name>`, which turns `<name>` off, the name as written. This is the option that
+    /// the statement turns on instead if there's an option called `no<name>`.
+    pub fn negated_from(&self) -> Option<&str> {
+        self.negated_from.as_deref()
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
+
+    /// The value of the option. For `set <name>` this is true, and for `set no<name>` it's false.
+    pub fn value(&self) -> &OptionValue {
+        &self.value
+    }
+
+    /// The span of the value. For `set <name>` and `set no<name>`, this is the span of the name.
+    pub fn value_span(&self) -> Span {
+        self.value_span
+    }
+}
+
+impl Spanned for Set {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The value of an option. Bare words other than `true` and `false` are strings, and lists are
+/// written as `[a b c]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    List(Vec<OptionValue>),
+    /// A string with variable references in it, which becomes a `Str` once the program is
+    /// evaluated.
+    Template(Template),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionValue::Bool(value) => write!(f, "{}", value),
+            OptionValue::Int(value) => write!(f, "{}", value),
+            OptionValue::Str(text) => write!(f, "\"{}\"", text.escape_debug()),
+            OptionValue::Template(template) => write!(f, "{}", template),
+            OptionValue::List(items) => {
+                write!(f, "[")?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "{}", item)?;
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// A `cmd` statement, defining a command that maps can use just like a built-in one.
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    pub(crate) name: String,
+    pub(crate) name_span: Span,
+    pub(crate) body: CmdBody,
+    pub(crate) span: Span,
+}
+
+impl Cmd {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
+
+    pub fn body(&self) -> &CmdBody {
+        &self.body
+    }
+}
+
+impl Spanned for Cmd {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A `source` statement, which loads the statements of another file in its place, as in
+/// `source "keys.rolfrc"`. A relative path is resolved against the directory of the file that
+/// contains the statement.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub(crate) path: Template,
+    pub(crate) path_span: Span,
+    pub(crate) span: Span,
+}
+
+impl Source {
+    /// The path of the file to load, which can refer to environment variables, as in
+    /// `source "$XDG_CONFIG_HOME/rolf/keys.rolfrc"`.
+    pub fn path(&self) -> &Template {
+        &self.path
+    }
+
+    pub fn path_span(&self) -> Span {
+        self.path_span
+    }
+}
+
+impl Spanned for Source {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// An `if` block, whose statements only apply when its condition holds on the machine that loads
+/// the config:
+///
+/// ```text
+/// if os macos {
+///     set opener open
+/// } else if env WAYLAND_DISPLAY {
+///     set opener xdg-open
+/// }
+/// ```
+///
+/// `else if` is parsed as an `else` branch holding a single `if` block.
+#[derive(Debug, Clone)]
+pub struct If {
+    pub(crate) condition: Condition,
+    pub(crate) condition_span: Span,
+    pub(crate) body: Program,
+    pub(crate) else_body: Option<Program>,
+    pub(crate) span: Span,
+}
+
+impl If {
+    pub fn condition(&self) -> &Condition {
+        &self.condition
+    }
+
+    pub fn condition_span(&self) -> Span {
+        self.condition_span
+    }
+
+    /// The statements that apply when the condition holds.
+    pub fn body(&self) -> &[Statement] {
+        &self.body
+    }
+
+    /// The statements that apply when the condition doesn't hold, if there is an `else` branch.
+    pub fn else_body(&self) -> Option<&[Statement]> {
+        self.else_body.as_deref()
+    }
+}
+
+impl Spanned for If {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A test of the machine that a config is loaded on. The patterns for `os`, `host` and `term` can
+/// use `*` to match any run of characters, as in `term xterm-*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// `os <pattern>`, matched against the operating system, such as `linux` or `macos`.
+    Os(String),
+    /// `host <pattern>`, matched case-insensitively against the hostname.
+    Host(String),
+    /// `term <pattern>`, matched against `$TERM`.
+    Term(String),
+    /// `env <name>`, which holds when the environment variable is set.
+    Env(String),
+    /// `exists <path>`, which holds when the file exists. A leading `~` is the home directory, and
+    /// the path can refer to variables.
+    Exists(Template),
+    /// `not <condition>`
+    Not(Box<Condition>),
+}
+
+/// Writes the condition the way it would be written in a config.
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, value) = match self {
+            Condition::Os(value) => ("os", value.as_str()),
+            Condition::Host(value) => ("host", value.as_str()),
+            Condition::Term(value) => ("term", value.as_str()),
+            Condition::Env(value) => ("env", value.as_str()),
+            Condition::Exists(path) => match path.as_text() {
+                Some(value) => ("exists", value),
+                None => return write!(f, "exists {}", path),
+            },
+            Condition::Not(condition) => return write!(f, "not {}", condition),
+        };
+
+        if !value.is_empty() && value.chars().all(is_word_char) {
+            write!(f, "{} {}", name, value)
+        } else {
+            write!(f, "{} \"{}\"", name, value.escape_debug())
+        }
+    }
+}
+
+/// Returns every statement in `program`, including the ones in both branches of `if` blocks, in
+/// the order they appear. Checks over a whole program use this so that they also catch mistakes
+/// in branches that don't apply on the current machine.
+pub(crate) fn all_statements(program: &[Statement]) -> Vec<&Statement> {
+    let mut statements = vec![];
+
+    for statement in program {
+        statements.push(statement);
+
+        if let Statement::If(if_block) = statement {
+            statements.extend(all_statements(if_block.body()));
+            statements.extend(if_block.else_body().map_or(vec![], all_statements));
+        }
+    }
+
+    statements
+}
+
+/// A `let` statement, defining a variable that later statements can refer to as `$name` or
+/// `${name}`, as in `let editor = nvim`.
+#[derive(Debug, Clone)]
+pub struct Let {
+    pub(crate) name: String,
+    pub(crate) name_span: Span,
+    pub(crate) value: Template,
+    pub(crate) span: Span,
+}
+
+impl Let {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
+
+    /// The value of the variable, which can refer to variables defined before it.
+    pub fn value(&self) -> &Template {
+        &self.value
+    }
+}
+
+impl Spanned for Let {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Text with variable references in it, from a bare word or a double-quoted string such as
+/// `"$dir/sub"`. Single-quoted strings are never templates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub(crate) parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplatePart {
+    Text(String),
+    /// A `$name` or `${name}` reference, or a `${name:-default}` or `${name:?message}` reference
+    /// with a fallback for when the variable is unset or empty.
+    Var {
+        name: String,
+        fallback: Option<Fallback>,
+        span: Span,
+    },
+}
+
+/// What a reference does when its variable is unset or empty, as in the shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fallback {
+    /// `${name:-default}` expands to the default instead, which can refer to other variables.
+    Default(Template),
+    /// `${name:?message}` is an error, which is reported with the message.
+    Error(String),
+}
+
+impl Template {
+    pub fn parts(&self) -> &[TemplatePart] {
+        &self.parts
+    }
+
+    /// Returns the text of a template without any references in it.
+    pub fn as_text(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [] => Some(""),
+            [TemplatePart::Text(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the text with each reference written back as a reference, for places that pass
+    /// text on without expanding it, such as the script of a shell command.
+    pub fn to_literal(&self) -> String {
+        self.write_parts(&|text: &str| text.to_string())
+    }
+
+    /// Joins the parts, escaping the text ones with `escape`. A reference is written as `$name`
+    /// unless it has a fallback, or the text after it would run on into the name, in which case
+    /// it's braced.
+    fn write_parts(&self, escape: &dyn Fn(&str) -> String) -> String {
+        // The text of a fallback ends at a `}`, so that has to be escaped as well.
+        let escape_fallback = |text: &str| escape(text).replace('}', "\\}");
+
+        let mut literal = String::new();
+
+        for (i, part) in self.parts.iter().enumerate() {
+            match part {
+                TemplatePart::Text(text) => literal.push_str(&escape(text)),
+                TemplatePart::Var {
+                    name,
+                    fallback: Some(Fallback::Default(default)),
+                    ..
+                } => {
+                    let default = default.write_parts(&escape_fallback);
+
+                    literal.push_str(&format!("${{{}:-{}}}", name, default));
+                }
+                TemplatePart::Var {
+                    name,
+                    fallback: Some(Fallback::Error(message)),
+                    ..
+                } => {
+                    literal.push_str(&format!("${{{}:?{}}}", name, escape_fallback(message)));
+                }
+                TemplatePart::Var { name, .. } => {
+                    let runs_on = match self.parts.get(i + 1) {
+                        Some(TemplatePart::Text(text)) => {
+                            text.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_')
+                        }
+                        _ => false,
+                    };
+
+                    if runs_on {
+                        literal.push_str(&format!("${{{}}}", name));
+                    } else {
+                        literal.push_str(&format!("${}", name));
+                    }
+                }
+            }
+        }
+
+        literal
+    }
+}
+
+impl From<String> for Template {
+    fn from(text: String) -> Self {
+        Self {
+            parts: vec![TemplatePart::Text(text)],
+        }
+    }
+}
+
+/// Writes the template as a double-quoted string, the way it would be written in a config.
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let literal =
+            self.write_parts(&|text: &str| text.escape_debug().to_string().replace('$', "\\$"));
+
+        write!(f, "\"{}\"", literal)
+    }
+}
+
+/// What a user-defined command does when it runs.
+#[derive(Debug, Clone)]
+pub enum CmdBody {
+    /// Run each command in turn, as in `cmd top-hidden top; set-hidden`.
+    Commands(Vec<Command>),
+    /// Run a shell command, as in `cmd trash 'mv "$f" ~/.trash'`.
+    Shell { script: String, span: Span },
+}
+
+/// An invocation of a command, e.g. `rename "new name"`.
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub(crate) name: String,
+    pub(crate) name_span: Span,
+    pub(crate) args: Vec<Arg>,
+    pub(crate) span: Span,
+}
+
+impl Command {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
+
+    pub fn args(&self) -> &[Arg] {
+        &self.args
+    }
+}
+
+impl Spanned for Command {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        for arg in &self.args {
+            write!(f, " {}", arg.kind)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Arg {
+    pub(crate) kind: ArgKind,
+    pub(crate) span: Span,
+}
+
+impl Arg {
+    pub fn kind(&self) -> &ArgKind {
+        &self.kind
+    }
+}
+
+impl Spanned for Arg {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The value of an argument to a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgKind {
+    /// A bare word, such as `top` or `~/Downloads`.
+    Id(String),
+    /// A quoted string.
+    Str(String),
+    Num(i64),
+    /// A word or double-quoted string with variable references in it, such as `$dir/sub`. These
+    /// become `Str` arguments once the program is evaluated.
+    Template(Template),
+}
+
+impl fmt::Display for ArgKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgKind::Id(name) => write!(f, "{}", name),
+            ArgKind::Str(text) => write!(f, "\"{}\"", text.escape_debug()),
+            ArgKind::Num(num) => write!(f, "{}", num),
+            ArgKind::Template(template) => write!(f, "{}", template),
+        }
+    }
+}
+
+/// Keys compare and hash equal when they describe the same key press, regardless of where they were
+/// written.
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub(crate) modifiers: Mods,
+    pub(crate) code: KeyCode,
+    pub(crate) span: Span,
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.modifiers == other.modifiers && self.code == other.code
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.modifiers.hash(state);
+        self.code.hash(state);
+    }
+}
+
+impl Key {
+    /// Creates a key that wasn't parsed from a config, such as one read from the terminal. Its
+    /// span is empty and at the start of the source.
+    pub fn new(modifiers: Mods, code: KeyCode) -> Self {
+        Self {
+            modifiers,
+            code,
+            span: Span::new(Location::START, Location::START),
+        }
+    }
+
+    pub fn modifiers(&self) -> Mods {
+        self.modifiers
+    }
+
+    /// The key itself, without any modifiers.
+    pub fn code(&self) -> KeyCode {
+        self.code
+    }
+}
+
+impl Spanned for Key {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for modifier in self.modifiers.iter() {
+            write!(f, "{}+", modifier)?;
+        }
+
+        write!(f, "{}", self.code)
+    }
+}
+
+/// Writes a sequence of keys the way it would be written in a config, e.g. `g g`.
+pub fn fmt_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The modes that rolf can be in, each of which has its own key bindings.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum Mode {
+    /// Browsing files. Bindings without a mode apply here.
+    #[default]
+    Normal,
+    /// Selecting a range of files.
+    Visual,
+    /// Typing on the command line.
+    Command,
+    /// Scrolling through the preview of a file.
+    Preview,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::Normal, Mode::Visual, Mode::Command, Mode::Preview];
+
+    /// Looks up a mode by the name it's written as in a config, case-insensitively. Returns None
+    /// for unknown names.
+    pub fn from_name(name: &str) -> Option<Mode> {
+        let mode = match name.to_lowercase().as_str() {
+            "normal" => Mode::Normal,
+            "visual" | "selection" => Mode::Visual,
+            "command" | "cmdline" => Mode::Command,
+            "preview" => Mode::Preview,
+            _ => return None,
+        };
+
+        Some(mode)
+    }
+}
+
+/// Writes the canonical name of the mode, the way it would be written in a config.
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Normal => write!(f, "normal"),
+            Mode::Visual => write!(f, "visual"),
+            Mode::Command => write!(f, "command"),
+            Mode::Preview => write!(f, "preview"),
+        }
+    }
+}
+
+/// A key on the keyboard, without any modifiers.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum KeyCode {
+    /// A key that types a character. The space bar is `Char(' ')`.
+    Char(char),
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// A function key, from `F(1)` to `F(24)`.
+    F(u8),
+}
+
+impl KeyCode {
+    /// The names of the keys that aren't single characters, for suggesting one in place of a
+    /// misspelled name. The function keys are left out, since `f1` to `f24` can't be mistyped as
+    /// another word.
+    pub(crate) const NAMES: &'static [&'static str] = &[
+        "enter",
+        "return",
+        "esc",
+        "escape",
+        "tab",
+        "backspace",
+        "delete",
+        "del",
+        "space",
+        "insert",
+        "home",
+        "end",
+        "pageup",
+        "pgup",
+        "pagedown",
+        "pgdn",
+        "up",
+        "down",
+        "left",
+        "right",
+    ];
+
+    /// Looks up a key by the name it's written as in a config. Any single character names itself,
+    /// and longer names are case-insensitive. Returns None for unknown names.
+    pub fn from_name(name: &str) -> Option<KeyCode> {
+        let mut chars = name.chars();
+
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Some(KeyCode::Char(ch));
+        }
+
+        let code = match name.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "space" => KeyCode::Char(' '),
+            "insert" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            lower => {
+                let digits = lower.strip_prefix('f')?;
+
+                if digits.starts_with('0') || !digits.chars().all(|ch| ch.is_ascii_digit()) {
+                    return None;
+                }
+
+                match digits.parse() {
+                    Ok(num @ 1..=24) => KeyCode::F(num),
+                    _ => return None,
+                }
+            }
+        };
+
+        Some(code)
+    }
+}
+
+/// Writes the canonical name of the key, the way it would be written in a config.
+impl fmt::Display for KeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(ch) if SPECIAL_CHARS.contains(ch) => write!(f, "\\{}", ch),
+            KeyCode::Char(ch) => write!(f, "{}", ch),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Insert => write!(f, "insert"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::F(num) => write!(f, "f{}", num),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum Mod {
+    Ctrl,
+    Shift,
+    Alt,
+}
+
+impl Mod {
+    pub const ALL: [Mod; 3] = [Mod::Ctrl, Mod::Shift, Mod::Alt];
+
+    fn bit(self) -> u8 {
+        match self {
+            Mod::Ctrl => 0b001,
+            Mod::Shift => 0b010,
+            Mod::Alt => 0b100,
+        }
+    }
+}
+
+impl fmt::Display for Mod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mod::Ctrl => write!(f, "ctrl"),
+            Mod::Shift => write!(f, "shift"),
+            Mod::Alt => write!(f, "alt"),
+        }
+    }
+}
+
+/// The set of modifiers held down for a key. The order they were written in doesn't matter, so
+/// `shift+ctrl+k` and `ctrl+shift+k` have the same modifiers.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct Mods(u8);
+
+impl Mods {
+    pub const NONE: Mods = Mods(0);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, modifier: Mod) -> bool {
+        self.0 & modifier.bit() != 0
+    }
+
+    /// Adds `modifier` to the set. Returns false if it was already there.
+    pub fn insert(&mut self, modifier: Mod) -> bool {
+        let was_present = self.contains(modifier);
+
+        self.0 |= modifier.bit();
+
+        !was_present
+    }
+
+    /// Iterates over the modifiers in canonical order: `ctrl`, `shift`, then `alt`.
+    pub fn iter(self) -> impl Iterator<Item = Mod> {
+        Mod::ALL
+            .into_iter()
+            .filter(move |modifier| self.contains(*modifier))
+    }
+}
+
+impl FromIterator<Mod> for Mods {
+    fn from_iter<I: IntoIterator<Item = Mod>>(iter: I) -> Self {
+        let mut mods = Mods::NONE;
+
+        for modifier in iter {
+            mods.insert(modifier);
+        }
+
+        mods
+    }
+}
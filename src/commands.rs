@@ -0,0 +1,170 @@
+//! A registry of the commands that a host supports, for checking the commands used in a program.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    ast::{all_statements, CmdBody, Command, Program, Statement},
+    suggest::suggest,
+    validate::{ValidationError, ValidationErrorKind},
+};
+
+/// The names of the commands that a host supports, which it fills in with its built-ins before
+/// checking a program.
+#[derive(Debug, Clone, Default)]
+pub struct CommandRegistry {
+    commands: BTreeSet<String>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a command to the registry.
+    pub fn insert(&mut self, name: impl Into<String>) {
+        self.commands.insert(name.into());
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.commands.contains(name)
+    }
+
+    /// Adds every command defined by a `cmd` statement in `program`, including those in either
+    /// branch of an `if` block.
+    pub fn insert_definitions(&mut self, program: &Program) {
+        for statement in all_statements(program) {
+            if let Statement::Cmd(cmd) = statement {
+                self.insert(cmd.name());
+            }
+        }
+    }
+
+    /// Checks that every command used by a `map` or `cmd` statement in `program` is either in the
+    /// registry or defined by a `cmd` statement somewhere in `program`. Returns an error for each
+    /// unknown command, in the order they appear, suggesting a known command with a similar name
+    /// where there is one.
+    pub fn check(&self, program: &Program) -> Vec<ValidationError> {
+        let mut known = self.clone();
+        known.insert_definitions(program);
+
+        let mut errors = vec![];
+
+        for statement in all_statements(program) {
+            let commands = match statement {
+                Statement::Map(map) => std::slice::from_ref(map.command()),
+                Statement::Cmd(cmd) => match cmd.body() {
+                    CmdBody::Commands(commands) => commands.as_slice(),
+                    CmdBody::Shell { .. } => &[],
+                },
+                _ => &[],
+            };
+
+            errors.extend(
+                commands
+                    .iter()
+                    .filter(|command| !known.contains(command.name()))
+                    .map(|command| known.unknown_command(command)),
+            );
+        }
+
+        errors
+    }
+
+    fn unknown_command(&self, command: &Command) -> ValidationError {
+        let suggestion = suggest(command.name(), self.commands.iter().map(String::as_str));
+
+        ValidationError::new(
+            command.name_span(),
+            ValidationErrorKind::UnknownCommand {
+                name: command.name().to_string(),
+                suggestion: suggestion.map(str::to_string),
+            },
+        )
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for CommandRegistry {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let mut registry = Self::new();
+
+        for name in iter {
+            registry.insert(name);
+        }
+
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config;
+
+    /// Checks `input` against a registry of a few built-ins, returning each error's message along
+    /// with the text it points at.
+    fn errors(input: &str) -> Vec<(String, &str)> {
+        let registry: CommandRegistry = ["up", "down", "top", "bottom", "open"]
+            .into_iter()
+            .collect();
+
+        registry
+            .check(&parse_config(input).unwrap())
+            .iter()
+            .map(|err| (err.kind().to_string(), err.span().text(input)))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_built_in_commands() {
+        assert!(errors("map j down\nmap k up").is_empty());
+    }
+
+    #[test]
+    fn suggests_a_similar_command() {
+        assert_eq!(
+            errors("map j dwon\nmap g g tpo"),
+            [
+                (
+                    "unknown command `dwon`, did you mean `down`?".to_string(),
+                    "dwon"
+                ),
+                (
+                    "unknown command `tpo`, did you mean `top`?".to_string(),
+                    "tpo"
+                ),
+            ]
+        );
+        assert_eq!(
+            errors("map q quit"),
+            [("unknown command `quit`".to_string(), "quit")]
+        );
+    }
+
+    #[test]
+    fn accepts_commands_defined_by_cmd() {
+        assert!(errors("map t trash\ncmd trash 'mv \"$f\" ~/.trash'").is_empty());
+        assert!(errors("cmd half-down down; down\nmap J half-down").is_empty());
+    }
+
+    #[test]
+    fn checks_the_commands_in_cmd_bodies() {
+        assert_eq!(
+            errors("cmd jump top; bottm"),
+            [(
+                "unknown command `bottm`, did you mean `bottom`?".to_string(),
+                "bottm"
+            )]
+        );
+    }
+
+    #[test]
+    fn suggests_commands_defined_by_cmd() {
+        assert_eq!(
+            errors("cmd trash 'rm'\nmap t trsh"),
+            [(
+                "unknown command `trsh`, did you mean `trash`?".to_string(),
+                "trsh"
+            )]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! A lossless syntax tree, which keeps the whitespace and comments that the parser throws away, so
+//! that tools can work with a config without destroying its layout.
+//!
+//! Every byte of the input belongs to exactly one token in the tree, so printing the tree gives
+//! back the input unchanged. Each statement that parsed is a node holding its tokens, along with
+//! the typed [`Statement`] that the parser made of them.
+
+use core::fmt;
+use std::{iter::Peekable, vec};
+
+use crate::{
+    ast::{Program, Statement},
+    lexer::{lex_lossless, Scanner, Token},
+    parse_tokens,
+    span::{FileId, Span, Spanned},
+    Error,
+};
+
+/// Lexes and parses `input` into a [`SyntaxTree`], carrying on past errors as
+/// [`parse_config_with_errors`](crate::parse_config_with_errors) does.
+pub fn parse_lossless(input: &str) -> SyntaxTree {
+    let (tokens, lex_errors) = lex_lossless(&mut Scanner::with_file(input, FileId::default()));
+    let parsed = parse_tokens(tokens.clone(), lex_errors);
+
+    let mut tokens = tokens.into_iter().peekable();
+    let elements = group(&mut tokens, &parsed.program, usize::MAX);
+
+    SyntaxTree {
+        text: input.to_string(),
+        elements,
+        errors: parsed.errors,
+    }
+}
+
+/// The result of [`parse_lossless`].
+///
+/// Whitespace, comments, newlines and the tokens of statements that failed to parse are kept as
+/// tokens between the statement nodes that they sit between, at the level of the tree where they
+/// were found: a comment inside an `if` block belongs to the block's node.
+#[derive(Debug)]
+pub struct SyntaxTree {
+    pub(crate) text: String,
+    pub(crate) elements: Vec<SyntaxElement>,
+    pub(crate) errors: Vec<Error>,
+}
+
+/// A token or statement in a [`SyntaxTree`].
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Token(Token),
+    Statement(StatementNode),
+}
+
+/// A statement in a [`SyntaxTree`], holding every token from its first to its last. The newline
+/// that ends it belongs to whatever contains the statement.
+#[derive(Debug, Clone)]
+pub struct StatementNode {
+    statement: Statement,
+    elements: Vec<SyntaxElement>,
+}
+
+impl SyntaxTree {
+    /// The text that the tree was parsed from.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The top-level tokens and statements, in order.
+    pub fn elements(&self) -> &[SyntaxElement] {
+        &self.elements
+    }
+
+    /// The top-level statements that parsed successfully.
+    pub fn statements(&self) -> impl Iterator<Item = &StatementNode> {
+        statements(&self.elements)
+    }
+
+    /// Every token in the tree, in order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![];
+        push_tokens(&self.elements, &mut tokens);
+
+        tokens
+    }
+
+    /// The typed AST of the statements that parsed, as [`parse_config_with_errors`] would return
+    /// it.
+    ///
+    /// [`parse_config_with_errors`]: crate::parse_config_with_errors
+    pub fn program(&self) -> Program {
+        self.statements()
+            .map(|node| node.statement.clone())
+            .collect()
+    }
+
+    /// Every error in the text, in the order they appear.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens() {
+            write!(f, "{}", token.source_text(&self.text))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StatementNode {
+    /// The typed view of this statement.
+    pub fn statement(&self) -> &Statement {
+        &self.statement
+    }
+
+    /// The tokens and nested statements that make up this statement, in order.
+    pub fn elements(&self) -> &[SyntaxElement] {
+        &self.elements
+    }
+
+    /// The statements nested in this one, which are those in the branches of an `if` block.
+    pub fn children(&self) -> impl Iterator<Item = &StatementNode> {
+        statements(&self.elements)
+    }
+
+    /// Every token in this statement, including those of nested statements, in order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![];
+        push_tokens(&self.elements, &mut tokens);
+
+        tokens
+    }
+}
+
+impl Spanned for StatementNode {
+    fn span(&self) -> Span {
+        self.statement.span()
+    }
+}
+
+impl Spanned for SyntaxElement {
+    fn span(&self) -> Span {
+        match self {
+            SyntaxElement::Token(token) => token.span(),
+            SyntaxElement::Statement(node) => node.span(),
+        }
+    }
+}
+
+/// Builds the elements for `statements`, taking the tokens that come before `end` from `tokens`.
+/// Tokens that aren't part of any of the statements are kept as they are, in between them.
+fn group(
+    tokens: &mut Peekable<vec::IntoIter<Token>>,
+    statements: &[Statement],
+    end: usize,
+) -> Vec<SyntaxElement> {
+    let mut elements = vec![];
+
+    for statement in statements {
+        let span = statement.span();
+
+        take_tokens(tokens, span.start.offset, &mut elements);
+
+        let children = match statement {
+            Statement::If(if_block) => if_block
+                .body()
+                .iter()
+                .chain(if_block.else_body().into_iter().flatten())
+                .cloned()
+                .collect(),
+            _ => vec![],
+        };
+
+        elements.push(SyntaxElement::Statement(StatementNode {
+            statement: statement.clone(),
+            elements: group(tokens, &children, span.end.offset),
+        }));
+    }
+
+    take_tokens(tokens, end, &mut elements);
+
+    elements
+}
+
+/// Moves every token that starts before `end` into `elements`.
+fn take_tokens(
+    tokens: &mut Peekable<vec::IntoIter<Token>>,
+    end: usize,
+    elements: &mut Vec<SyntaxElement>,
+) {
+    while let Some(token) = tokens.next_if(|token| token.span().start.offset < end) {
+        elements.push(SyntaxElement::Token(token));
+    }
+}
+
+fn statements(elements: &[SyntaxElement]) -> impl Iterator<Item = &StatementNode> {
+    elements.iter().filter_map(|element| match element {
+        SyntaxElement::Statement(node) => Some(node),
+        SyntaxElement::Token(_) => None,
+    })
+}
+
+fn push_tokens<'a>(elements: &'a [SyntaxElement], tokens: &mut Vec<&'a Token>) {
+    for element in elements {
+        match element {
+            SyntaxElement::Token(token) => tokens.push(token),
+            SyntaxElement::Statement(node) => push_tokens(&node.elements, tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config_with_errors;
+
+    const INPUTS: &[&str] = &[
+        "",
+        "\n\n",
+        "map j down\n",
+        "# comment only",
+        "  map   j\tdown   # trailing\n\n\nset hidden true",
+        "if os linux {\n    # inner\n    set hidden true\n} else if env X { map k up }\n",
+        "let dir = \"$HOME/x\"\ncmd trash 'mv \"$f\" ~/.trash'\nmap g\\  x\n",
+        "map é ünïcode\n",
+        "bogus line here\nmap j\nmap \"unterminated\nset x [1 2\r\nmap k up\r\n",
+        "if os linux { map q }\nmap j down\n",
+    ];
+
+    #[test]
+    fn printing_the_tree_gives_back_the_input() {
+        for input in INPUTS {
+            assert_eq!(parse_lossless(input).to_string(), *input);
+        }
+    }
+
+    #[test]
+    fn tokens_cover_every_byte_in_order() {
+        for input in INPUTS {
+            let tree = parse_lossless(input);
+            let mut offset = 0;
+
+            for token in tree.tokens() {
+                assert_eq!(token.span().start.offset, offset, "in {:?}", input);
+                offset = token.span().end.offset;
+            }
+
+            assert_eq!(offset, input.len(), "in {:?}", input);
+        }
+    }
+
+    #[test]
+    fn program_matches_the_parser() {
+        for input in INPUTS {
+            let tree = parse_lossless(input);
+            let parsed = parse_config_with_errors(input);
+
+            assert_eq!(
+                format!("{:?}", tree.program()),
+                format!("{:?}", parsed.program)
+            );
+            assert_eq!(tree.errors().len(), parsed.errors.len());
+        }
+    }
+
+    #[test]
+    fn comments_in_blocks_belong_to_the_block() {
+        let tree = parse_lossless("if os linux {\n    # inner\n    set hidden true\n}\n");
+        let node = tree.statements().next().unwrap();
+
+        assert!(node.elements().iter().any(|element| matches!(
+            element,
+            SyntaxElement::Token(token) if token.source_text(tree.text()) == "# inner"
+        )));
+        assert_eq!(node.children().count(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+//! Rendering of errors as rustc-style reports that quote the offending source.
+
+use std::fmt::Write;
+
+use crate::span::{FileId, Location, Span};
+
+/// Where in the source an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    EOF,
+    Span(Span),
+}
+
+/// An error that points at a place in a config file, which can be rendered as a report that
+/// quotes it.
+pub trait Diagnostic {
+    /// Where in the source the error occurred.
+    fn position(&self) -> Position;
+    /// What went wrong, without saying where.
+    fn message(&self) -> String;
+    /// Renders this error as a report that quotes the offending line of `source`, which is the
+    /// text of the file called `file_name`.
+    fn render(&self, file_name: &str, source: &str) -> String {
+        render(file_name, source, self.position(), &self.message())
+    }
+}
+
+/// Renders `message` as a report that underlines `position`:
+///
+/// ```text
+/// error: expected a command name after key `ctrl+k`
+///  --> rolfrc:1:11
+///   |
+/// 1 | map ctrl+k
+///   |           ^
+/// ```
+pub(crate) fn render(file_name: &str, source: &str, position: Position, message: &str) -> String {
+    let (line, col, span_text) = match position {
+        Position::Span(span) => (span.start.line, span.start.col, span.text(source)),
+        Position::EOF => {
+            let (line, col) = eof_line_col(source);
+
+            (line, col, "")
+        }
+    };
+
+    let source_line = source.lines().nth(line - 1).unwrap_or("");
+
+    // Spans that cover several lines (or end with a newline) are only underlined up to the end of
+    // the first one.
+    let width = span_text
+        .split('\n')
+        .next()
+        .map_or(0, |first_line| first_line.chars().count());
+    let gutter = " ".repeat(line.to_string().len());
+
+    // Keep tabs in the padding so that the caret lines up with the quoted line in a terminal.
+    let padding: String = source_line
+        .chars()
+        .take(col - 1)
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let mut report = String::new();
+
+    writeln!(report, "error: {}", message).unwrap();
+    writeln!(report, "{}--> {}:{}:{}", gutter, file_name, line, col).unwrap();
+    writeln!(report, "{} |", gutter).unwrap();
+    writeln!(report, "{} | {}", line, source_line).unwrap();
+    write!(
+        report,
+        "{} | {}{}",
+        gutter,
+        padding,
+        "^".repeat(width.max(1))
+    )
+    .unwrap();
+
+    report
+}
+
+/// Returns an empty span in `file` that renders the same way as `Position::EOF` does for `source`.
+pub(crate) fn eof_span(source: &str, file: FileId) -> Span {
+    let (line, col) = eof_line_col(source);
+    let offset = source.strip_suffix('\n').unwrap_or(source).len();
+    let location = Location { offset, line, col };
+
+    Span {
+        start: location,
+        end: location,
+        file,
+    }
+}
+
+/// Returns the line and column just past the last character of `source`, ignoring a trailing
+/// newline so that the report quotes the last line with content.
+fn eof_line_col(source: &str) -> (usize, usize) {
+    let trimmed = source.strip_suffix('\n').unwrap_or(source);
+
+    match trimmed.rsplit_once('\n') {
+        Some((before, last)) => (before.matches('\n').count() + 2, last.chars().count() + 1),
+        None => (1, trimmed.chars().count() + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_config, Diagnostic};
+
+    fn report(input: &str) -> String {
+        parse_config(input).unwrap_err().render("rolfrc", input)
+    }
+
+    #[test]
+    fn reports_quote_the_line_and_underline_the_error() {
+        let report = report("map j down\n  bogus j down\n");
+        let (message, rest) = report.split_once('\n').unwrap();
+
+        assert!(message.starts_with("error: "), "{}", message);
+        assert_eq!(
+            rest,
+            " --> rolfrc:2:3\n\
+             \x20 |\n\
+             2 |   bogus j down\n\
+             \x20 |   ^^^^^"
+        );
+    }
+
+    #[test]
+    fn errors_at_the_end_point_past_the_last_line() {
+        assert_eq!(
+            report("map j down\nmap ctrl+k\n"),
+            "error: expected a command name after key `ctrl+k`\n\
+             \x20--> rolfrc:2:11\n\
+             \x20 |\n\
+             2 | map ctrl+k\n\
+             \x20 |           ^"
+        );
+    }
+}
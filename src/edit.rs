@@ -0,0 +1,447 @@
+//! Editing a config file in place, such as from a "remap this key" screen, changing only the text
+//! of the statements that are edited so that the rest of the file is left as the user wrote it.
+
+use std::{mem, ops::Range};
+
+use crate::{
+    ast::{Key, Mode, Statement},
+    cst::{parse_lossless, StatementNode, SyntaxTree},
+    lexer::TokenKind,
+    parser::{ParseError, ParseErrorKind},
+    span::Spanned,
+    Error,
+};
+
+/// A config file that is being edited. Each edit changes as little of the text as it can, leaving
+/// every other line, comment and blank line untouched, and returns the change it made as a
+/// [`TextEdit`] so that a host can apply the same change to a buffer of its own.
+///
+/// Only top-level statements are edited. Statements inside `if` blocks are left alone, since
+/// changing one would only change what happens on some machines.
+#[derive(Debug)]
+pub struct Document {
+    tree: SyntaxTree,
+}
+
+/// A change to the text of a document: the bytes in `range` are replaced with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// What a statement sets, which a later statement that sets the same thing overrides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The binding for a sequence of keys in a mode, set by `map`.
+    Map { mode: Mode, keys: Vec<Key> },
+    /// An option, set by `set`.
+    Set(String),
+    /// A user-defined command, defined by `cmd`.
+    Cmd(String),
+    /// A variable, defined by `let`.
+    Let(String),
+}
+
+impl Document {
+    /// Parses `text`, carrying on past errors. Statements that don't parse are kept as they are,
+    /// and can't be edited.
+    pub fn parse(text: &str) -> Self {
+        Self {
+            tree: parse_lossless(text),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        self.tree.text()
+    }
+
+    /// The syntax tree of the current text.
+    pub fn tree(&self) -> &SyntaxTree {
+        &self.tree
+    }
+
+    /// Returns the top-level statement that sets `target`. If more than one does, this is the
+    /// last of them, since that's the one that takes effect.
+    pub fn find(&self, target: &Target) -> Option<&StatementNode> {
+        self.tree
+            .statements()
+            .filter(|node| Target::of(node.statement()).as_ref() == Some(target))
+            .last()
+    }
+
+    /// Adds `statement`, which is the text of a single statement such as `map ctrl+k up`, or
+    /// replaces the statement that sets the same thing.
+    ///
+    /// A replacement takes the place of the statement found by [`Document::find`], and the edit
+    /// only covers the part of it that changed. A comment after the old statement is kept, unless
+    /// `statement` ends with a comment of its own, which replaces it.
+    ///
+    /// A new statement goes on a line of its own after the last top-level statement of the same
+    /// kind, so that a new `map` joins the other bindings, or at the end of the file if there isn't
+    /// one. If a later `map` or `unmap` would undo the statement, as `map g g` does to `map g`, the
+    /// new statement goes after that instead, even if there's an old one to replace.
+    pub fn set(&mut self, statement: &str) -> Result<TextEdit, Error> {
+        let text = statement.trim();
+        let (statement, has_comment) = parse_statement(text)?;
+
+        let target = Target::of(&statement);
+        let found = target.as_ref().and_then(|target| self.find(target));
+        let undone_by = target.as_ref().and_then(|target| {
+            let start = found.map_or(0, |node| node.span().end.offset);
+
+            self.tree
+                .statements()
+                .filter(|node| node.span().start.offset >= start)
+                .filter(|node| target.is_undone_by(node.statement()))
+                .last()
+        });
+
+        let edit = match (found, undone_by) {
+            (Some(node), None) => {
+                let source = self.text();
+                let span = node.span();
+
+                let end = if has_comment {
+                    source[span.end.offset..]
+                        .find('\n')
+                        .map_or(source.len(), |newline| span.end.offset + newline)
+                } else {
+                    span.end.offset
+                };
+
+                replacement(source, span.start.offset..end, text)
+            }
+            (_, Some(node)) => self.insertion(Some(node), text),
+            (None, None) => {
+                let same_kind = self.tree.statements().filter(|node| {
+                    mem::discriminant(node.statement()) == mem::discriminant(&statement)
+                });
+
+                self.insertion(same_kind.last(), text)
+            }
+        };
+
+        self.apply(&edit);
+
+        Ok(edit)
+    }
+
+    /// Removes every top-level statement that sets `target`, along with the rest of its line,
+    /// such as a comment after it. Returns the edits that were made, last first, so that applying
+    /// them in order leaves the ranges of the later ones valid.
+    pub fn remove(&mut self, target: &Target) -> Vec<TextEdit> {
+        let mut edits: Vec<TextEdit> = self
+            .tree
+            .statements()
+            .filter(|node| Target::of(node.statement()).as_ref() == Some(target))
+            .map(|node| self.removal(node))
+            .collect();
+        edits.reverse();
+
+        for edit in &edits {
+            self.apply(edit);
+        }
+
+        edits
+    }
+
+    /// Returns the edit that adds a statement, whose text is `text`, on a line of its own after
+    /// the line holding `after`, or at the end of the file.
+    fn insertion(&self, after: Option<&StatementNode>, text: &str) -> TextEdit {
+        let source = self.text();
+
+        let line_end = after.and_then(|node| {
+            let end = node.span().end.offset;
+
+            source[end..].find('\n').map(|newline| end + newline + 1)
+        });
+
+        match line_end {
+            Some(offset) => TextEdit {
+                range: offset..offset,
+                text: format!("{}\n", text),
+            },
+            None if source.is_empty() || source.ends_with('\n') => TextEdit {
+                range: source.len()..source.len(),
+                text: format!("{}\n", text),
+            },
+            // Keep a file that didn't end in a newline that way.
+            None => TextEdit {
+                range: source.len()..source.len(),
+                text: format!("\n{}", text),
+            },
+        }
+    }
+
+    /// Returns the edit that removes the line holding `node`.
+    fn removal(&self, node: &StatementNode) -> TextEdit {
+        let source = self.text();
+        let span = node.span();
+
+        let line_start = source[..span.start.offset]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+        let start = if source[line_start..span.start.offset].trim().is_empty() {
+            line_start
+        } else {
+            span.start.offset
+        };
+
+        let end = source[span.end.offset..]
+            .find('\n')
+            .map_or(source.len(), |newline| span.end.offset + newline + 1);
+
+        TextEdit {
+            range: start..end,
+            text: String::new(),
+        }
+    }
+
+    fn apply(&mut self, edit: &TextEdit) {
+        self.tree = parse_lossless(&edit.apply(self.text()));
+    }
+}
+
+impl TextEdit {
+    /// Applies this edit to `text`, which has to be the text that the edit was made for.
+    pub fn apply(&self, text: &str) -> String {
+        let mut edited = text.to_string();
+        edited.replace_range(self.range.clone(), &self.text);
+
+        edited
+    }
+}
+
+impl Target {
+    /// Returns what `statement` sets, if it's a statement that another can override. `unmap`,
+    /// `if` and `source` statements don't set anything of their own.
+    pub fn of(statement: &Statement) -> Option<Target> {
+        match statement {
+            Statement::Map(map) => Some(Target::Map {
+                mode: map.mode(),
+                keys: map.keys().to_vec(),
+            }),
+            Statement::Set(set) => Some(Target::Set(set.name().to_string())),
+            Statement::Cmd(cmd) => Some(Target::Cmd(cmd.name().to_string())),
+            Statement::Let(let_statement) => Some(Target::Let(let_statement.name().to_string())),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `statement` undoes a statement that sets this target without setting it
+    /// again: a `map` of a sequence that the target's keys start with or that starts with them,
+    /// or an `unmap` that removes the target's binding.
+    fn is_undone_by(&self, statement: &Statement) -> bool {
+        let Target::Map { mode, keys } = self else {
+            return false;
+        };
+
+        match statement {
+            Statement::Map(map) => {
+                map.mode() == *mode
+                    && map.keys() != keys.as_slice()
+                    && (map.keys().starts_with(keys) || keys.starts_with(map.keys()))
+            }
+            Statement::Unmap(unmap) if unmap.is_prefix() => {
+                unmap.mode() == *mode && keys.starts_with(unmap.keys())
+            }
+            Statement::Unmap(unmap) => unmap.mode() == *mode && unmap.keys() == keys.as_slice(),
+            _ => false,
+        }
+    }
+}
+
+/// Parses `text` as exactly one statement, returning it along with whether a comment follows it.
+fn parse_statement(text: &str) -> Result<(Statement, bool), Error> {
+    let mut tree = parse_lossless(text);
+
+    if !tree.errors.is_empty() {
+        return Err(tree.errors.remove(0));
+    }
+
+    let mut program = tree.program();
+
+    let statement = match program.len() {
+        0 => return Err(ParseError::new(ParseErrorKind::ExpectedStatement).into()),
+        1 => program.remove(0),
+        _ => {
+            return Err(
+                ParseError::new_span(program[1].span(), ParseErrorKind::RemainingTokens).into(),
+            )
+        }
+    };
+
+    let end = statement.span().end.offset;
+    let has_comment = tree.tokens().iter().any(|token| {
+        matches!(token.kind(), TokenKind::Comment(_)) && token.span().start.offset >= end
+    });
+
+    Ok((statement, has_comment))
+}
+
+/// Returns the edit that replaces the text in `range` of `source` with `text`, trimmed down to
+/// the part that actually differs.
+fn replacement(source: &str, range: Range<usize>, text: &str) -> TextEdit {
+    let old = &source[range.clone()];
+
+    let prefix: usize = old
+        .chars()
+        .zip(text.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(ch, _)| ch.len_utf8())
+        .sum();
+
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(text[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(ch, _)| ch.len_utf8())
+        .sum();
+
+    TextEdit {
+        range: range.start + prefix..range.end - suffix,
+        text: text[prefix..text.len() - suffix].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "# Navigation\nmap j down # move\nmap k up\n\nset hidden true\n";
+
+    fn edited(text: &str, statement: &str) -> (TextEdit, String) {
+        let mut document = Document::parse(text);
+        let edit = document.set(statement).unwrap();
+
+        assert_eq!(edit.apply(text), document.text());
+
+        (edit, document.text().to_string())
+    }
+
+    #[test]
+    fn replacing_only_changes_what_differs() {
+        let (edit, text) = edited(CONFIG, "map j bottom");
+
+        assert_eq!(edit.text, "bottom");
+        assert_eq!(&CONFIG[edit.range], "down");
+        assert_eq!(
+            text,
+            "# Navigation\nmap j bottom # move\nmap k up\n\nset hidden true\n"
+        );
+    }
+
+    #[test]
+    fn replacing_with_a_comment_replaces_the_old_one() {
+        let (_, text) = edited(CONFIG, "map j up # new");
+
+        assert_eq!(
+            text,
+            "# Navigation\nmap j up # new\nmap k up\n\nset hidden true\n"
+        );
+    }
+
+    #[test]
+    fn replacing_an_unchanged_statement_changes_nothing() {
+        let (edit, text) = edited(CONFIG, "map k up");
+
+        assert!(edit.range.is_empty() && edit.text.is_empty());
+        assert_eq!(text, CONFIG);
+    }
+
+    #[test]
+    fn inserting_goes_after_the_same_kind() {
+        let (edit, text) = edited(CONFIG, "map g top");
+
+        assert!(edit.range.is_empty());
+        assert_eq!(
+            text,
+            "# Navigation\nmap j down # move\nmap k up\nmap g top\n\nset hidden true\n"
+        );
+    }
+
+    #[test]
+    fn inserting_goes_at_the_end_without_the_same_kind() {
+        assert_eq!(
+            edited(CONFIG, "let a = 1").1,
+            format!("{}let a = 1\n", CONFIG)
+        );
+        assert_eq!(edited("map j down", "let a = 1").1, "map j down\nlet a = 1");
+        assert_eq!(edited("", "map j down").1, "map j down\n");
+    }
+
+    #[test]
+    fn statements_in_blocks_are_left_alone() {
+        let text = "if os linux {\n    map j down\n}\n";
+
+        assert_eq!(
+            edited(text, "map j up").1,
+            "if os linux {\n    map j down\n}\nmap j up\n"
+        );
+    }
+
+    #[test]
+    fn inserting_after_a_statement_that_would_undo_it() {
+        assert_eq!(
+            edited("map j down\nmap j j foo\n", "map j up").1,
+            "map j down\nmap j j foo\nmap j up\n"
+        );
+        assert_eq!(
+            edited("map g g top\nunmap g *\nmap k up\n", "map g g bottom").1,
+            "map g g top\nunmap g *\nmap g g bottom\nmap k up\n"
+        );
+        assert_eq!(
+            edited("map k up\nunmap j", "map j down").1,
+            "map k up\nunmap j\nmap j down"
+        );
+    }
+
+    #[test]
+    fn replacing_ignores_other_modes_and_keys() {
+        assert_eq!(
+            edited(
+                "map j down\nmap[visual] j j x\nunmap k\nmap k j y\nunmap j j\n",
+                "map j up"
+            )
+            .1,
+            "map j up\nmap[visual] j j x\nunmap k\nmap k j y\nunmap j j\n"
+        );
+    }
+
+    #[test]
+    fn removing_only_deletes_the_line() {
+        let mut document = Document::parse(CONFIG);
+        let target = Target::of(&Document::parse("map j x").tree().program()[0]).unwrap();
+        let edits = document.remove(&target);
+
+        assert_eq!(edits.len(), 1);
+        assert_eq!(&CONFIG[edits[0].range.clone()], "map j down # move\n");
+        assert_eq!(
+            document.text(),
+            "# Navigation\nmap k up\n\nset hidden true\n"
+        );
+    }
+
+    #[test]
+    fn removing_every_statement_for_a_target() {
+        let text = "set hidden true\nmap j down\nset hidden false\n";
+        let mut document = Document::parse(text);
+        let edits = document.remove(&Target::Set("hidden".to_string()));
+
+        assert_eq!(edits.len(), 2);
+        assert!(edits[0].range.start > edits[1].range.start);
+        assert_eq!(document.text(), "map j down\n");
+    }
+
+    #[test]
+    fn set_takes_a_single_statement() {
+        let mut document = Document::parse(CONFIG);
+
+        assert!(document.set("").is_err());
+        assert!(document.set("map j down\nmap k up").is_err());
+        assert!(document.set("map j").is_err());
+        assert_eq!(document.text(), CONFIG);
+    }
+}
@@ -0,0 +1,361 @@
+//! Evaluation of a program against the machine that loads it, which picks the branches of `if`
+//! blocks and expands variables.
+
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use crate::{
+    ast::{
+        Arg, ArgKind, Cmd, CmdBody, Command, Condition, Fallback, Map, OptionValue, Program, Set,
+        Statement, Template, TemplatePart,
+    },
+    validate::{ValidationError, ValidationErrorKind},
+};
+
+/// What conditions are tested against. [`Context::from_system`] describes the current machine,
+/// while hosts and tools that want to see how a config behaves elsewhere can fill one in by hand.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    /// The operating system, named as in [`std::env::consts::OS`], such as `linux` or `macos`.
+    pub os: String,
+    pub hostname: String,
+    /// The environment variables, which `term` and `env` conditions test, and which references
+    /// fall back to when no `let` statement defines them.
+    pub env: HashMap<String, String>,
+}
+
+impl Context {
+    /// Creates an empty context, in which only `not` and `exists` conditions can hold.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a context describing the machine that this is running on.
+    pub fn from_system() -> Self {
+        Self {
+            os: env::consts::OS.to_string(),
+            hostname: system_hostname().unwrap_or_default(),
+            env: env::vars().collect(),
+        }
+    }
+
+    /// Returns true if `condition` holds in this context. Any references in an `exists` path are
+    /// expanded from the environment alone, since there are no `let` statements to define them.
+    pub fn test(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Os(pattern) => glob_match(pattern, &self.os),
+            Condition::Host(pattern) => {
+                glob_match(&pattern.to_lowercase(), &self.hostname.to_lowercase())
+            }
+            Condition::Term(pattern) => self
+                .env
+                .get("TERM")
+                .is_some_and(|term| glob_match(pattern, term)),
+            Condition::Env(name) => self.env.contains_key(name),
+            Condition::Exists(path) => {
+                let path = expand(path, &|name| self.env.get(name).cloned(), &mut vec![]);
+
+                self.expand_home(&path).exists()
+            }
+            Condition::Not(condition) => !self.test(condition),
+        }
+    }
+
+    /// Returns the statements of `program` that apply in this context, along with an error for
+    /// each reference to an undefined variable in them.
+    ///
+    /// Each `if` block is replaced by the statements of whichever branch applies, `let`
+    /// statements are applied and removed, and variable references are expanded into plain
+    /// strings. The result can be passed straight to
+    /// [`Keymap::from_program`](crate::Keymap::from_program) or
+    /// [`OptionSchema::resolve`](crate::OptionSchema::resolve).
+    pub fn evaluate(&self, program: &[Statement]) -> (Program, Vec<ValidationError>) {
+        let mut evaluator = Evaluator {
+            context: self,
+            variables: HashMap::new(),
+            errors: vec![],
+        };
+
+        let program = evaluator.evaluate(program);
+
+        (program, evaluator.errors)
+    }
+
+    /// Replaces a leading `~` in `path` with `$HOME`, if it's set.
+    pub(crate) fn expand_home(&self, path: &str) -> PathBuf {
+        match (path.strip_prefix('~'), self.env.get("HOME")) {
+            (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                PathBuf::from(format!("{}{}", home, rest))
+            }
+            _ => PathBuf::from(path),
+        }
+    }
+}
+
+/// The state of an evaluation, which carries the variables defined so far from one statement to
+/// the next.
+struct Evaluator<'a> {
+    context: &'a Context,
+    variables: HashMap<String, String>,
+    errors: Vec<ValidationError>,
+}
+
+impl Evaluator<'_> {
+    fn evaluate(&mut self, program: &[Statement]) -> Program {
+        let mut effective = vec![];
+
+        for statement in program {
+            match statement {
+                Statement::If(if_block) => {
+                    if self.test(if_block.condition()) {
+                        effective.extend(self.evaluate(if_block.body()));
+                    } else if let Some(else_body) = if_block.else_body() {
+                        effective.extend(self.evaluate(else_body));
+                    }
+                }
+                Statement::Let(let_statement) => {
+                    let value = self.expand(let_statement.value());
+
+                    self.variables.insert(let_statement.name.clone(), value);
+                }
+                Statement::Map(map) => effective.push(Statement::Map(Map {
+                    command: self.expand_command(map.command()),
+                    ..map.clone()
+                })),
+                Statement::Set(set) => effective.push(Statement::Set(Set {
+                    value: self.expand_value(set.value()),
+                    ..set.clone()
+                })),
+                Statement::Cmd(cmd) => {
+                    let body = match cmd.body() {
+                        CmdBody::Commands(commands) => CmdBody::Commands(
+                            commands
+                                .iter()
+                                .map(|command| self.expand_command(command))
+                                .collect(),
+                        ),
+                        body => body.clone(),
+                    };
+
+                    effective.push(Statement::Cmd(Cmd {
+                        body,
+                        ..cmd.clone()
+                    }));
+                }
+                statement => effective.push(statement.clone()),
+            }
+        }
+
+        effective
+    }
+
+    /// Tests `condition` like [`Context::test`], but with the variables defined so far.
+    fn test(&mut self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Exists(path) => {
+                let path = self.expand(path);
+
+                self.context.expand_home(&path).exists()
+            }
+            Condition::Not(condition) => !self.test(condition),
+            condition => self.context.test(condition),
+        }
+    }
+
+    fn expand_command(&mut self, command: &Command) -> Command {
+        let args = command
+            .args()
+            .iter()
+            .map(|arg| match arg.kind() {
+                ArgKind::Template(template) => Arg {
+                    kind: ArgKind::Str(self.expand(template)),
+                    span: arg.span,
+                },
+                _ => arg.clone(),
+            })
+            .collect();
+
+        Command {
+            args,
+            ..command.clone()
+        }
+    }
+
+    fn expand_value(&mut self, value: &OptionValue) -> OptionValue {
+        match value {
+            OptionValue::Template(template) => OptionValue::Str(self.expand(template)),
+            OptionValue::List(items) => {
+                OptionValue::List(items.iter().map(|item| self.expand_value(item)).collect())
+            }
+            value => value.clone(),
+        }
+    }
+
+    /// Expands the references in `template` to the variables defined so far, falling back to the
+    /// environment.
+    fn expand(&mut self, template: &Template) -> String {
+        let lookup = |name: &str| {
+            self.variables
+                .get(name)
+                .or_else(|| self.context.env.get(name))
+                .cloned()
+        };
+
+        expand(template, &lookup, &mut self.errors)
+    }
+}
+
+/// Expands the references in `template`, looking up each variable with `lookup`. A reference that
+/// can't be expanded is reported in `errors` and expands to nothing.
+pub(crate) fn expand(
+    template: &Template,
+    lookup: &impl Fn(&str) -> Option<String>,
+    errors: &mut Vec<ValidationError>,
+) -> String {
+    let mut text = String::new();
+
+    for part in template.parts() {
+        let (name, fallback, span) = match part {
+            TemplatePart::Text(part) => {
+                text.push_str(part);
+                continue;
+            }
+            TemplatePart::Var {
+                name,
+                fallback,
+                span,
+            } => (name, fallback, *span),
+        };
+
+        // As in the shell, a fallback applies to an empty variable as well as an unset one.
+        let value = lookup(name).filter(|value| !value.is_empty() || fallback.is_none());
+
+        match (value, fallback) {
+            (Some(value), _) => text.push_str(&value),
+            (None, Some(Fallback::Default(default))) => {
+                text.push_str(&expand(default, lookup, errors))
+            }
+            (None, Some(Fallback::Error(message))) => errors.push(ValidationError::new(
+                span,
+                ValidationErrorKind::MissingVariable {
+                    name: name.clone(),
+                    message: message.clone(),
+                },
+            )),
+            (None, None) => errors.push(ValidationError::new(
+                span,
+                ValidationErrorKind::UndefinedVariable(name.clone()),
+            )),
+        }
+    }
+
+    text
+}
+
+/// Looks up the hostname without shelling out, which is enough for the platforms rolf runs on.
+fn system_hostname() -> Option<String> {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+/// Returns true if `text` matches `pattern`, in which `*` matches any run of characters and every
+/// other character matches itself.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => match text.strip_prefix(prefix) {
+            // Try every split of the remaining text, since `*` could match any amount of it.
+            Some(text) => text
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain([text.len()])
+                .any(|index| glob_match(rest, &text[index..])),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config;
+
+    /// Evaluates `let value = <template>` in an environment where `SET` is `x` and `EMPTY` is
+    /// empty, returning the value it gets along with the messages of any errors.
+    fn expand_value(template: &str) -> (String, Vec<String>) {
+        let context = Context {
+            env: [
+                ("SET".to_string(), "x".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+            .into(),
+            ..Context::new()
+        };
+
+        let program =
+            parse_config(&format!("let value = \"{}\"\nset out $value", template)).unwrap();
+        let (program, errors) = context.evaluate(&program);
+
+        let value = match &program[..] {
+            [Statement::Set(set)] => match set.value() {
+                OptionValue::Str(value) => value.clone(),
+                value => panic!("expected a string, got {:?}", value),
+            },
+            program => panic!("expected a single set statement, got {:?}", program),
+        };
+
+        (
+            value,
+            errors.iter().map(|err| err.kind().to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn default_applies_to_unset_and_empty_variables() {
+        assert_eq!(expand_value("${SET:-d}"), ("x".to_string(), vec![]));
+        assert_eq!(expand_value("${EMPTY:-d}"), ("d".to_string(), vec![]));
+        assert_eq!(expand_value("${UNSET:-d}"), ("d".to_string(), vec![]));
+        assert_eq!(
+            expand_value("${UNSET:-$SET/d}"),
+            ("x/d".to_string(), vec![])
+        );
+        assert_eq!(expand_value("${UNSET:-}"), (String::new(), vec![]));
+    }
+
+    #[test]
+    fn error_applies_to_unset_and_empty_variables() {
+        assert_eq!(expand_value("${SET:?no set}"), ("x".to_string(), vec![]));
+        assert_eq!(
+            expand_value("${EMPTY:?needs a value}"),
+            (
+                String::new(),
+                vec!["variable `EMPTY` is unset or empty: needs a value".to_string()]
+            )
+        );
+        assert_eq!(
+            expand_value("a${UNSET:?}b"),
+            (
+                "ab".to_string(),
+                vec!["variable `UNSET` is unset or empty".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn without_a_fallback_only_unset_variables_are_errors() {
+        assert_eq!(expand_value("[$EMPTY]"), ("[]".to_string(), vec![]));
+        assert_eq!(
+            expand_value("[$UNSET]"),
+            (
+                "[]".to_string(),
+                vec![
+                    "variable `UNSET` isn't defined by a `let` statement or set in the environment"
+                        .to_string()
+                ]
+            )
+        );
+    }
+}
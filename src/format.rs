@@ -0,0 +1,514 @@
+//! Formatting config files in one canonical style, so that shared configs don't drift apart in
+//! spacing and in how keys are written.
+
+use crate::{
+    ast::{fmt_keys, Map, Mode, Statement, Unmap},
+    cst::{parse_lossless, StatementNode, SyntaxElement},
+    lexer::{Token, TokenKind},
+    parse_config,
+    span::{Span, Spanned},
+    Error,
+};
+
+/// The indentation for each level of `if` block.
+const INDENT: &str = "    ";
+
+/// Formats a config file in the canonical style, failing on the first error in it so that a file
+/// that doesn't parse is never rewritten.
+///
+/// The canonical style:
+///
+/// - puts one space between the words of a statement, leaving words that were written touching,
+///   such as `map[visual]` and `[1 2 3]`, as they are, and writes the `;` between commands as
+///   `; `
+/// - indents the statements in `if` blocks by four spaces, with each on its own line
+/// - writes keys with their modifiers in a fixed order and with their canonical names, so
+///   `shift+ctrl+Return` becomes `ctrl+shift+enter`, and spells out compact sequences like `gg`
+/// - lines up the commands of consecutive `map` lines at the same level
+/// - keeps every comment, with one space before a comment at the end of a line
+/// - keeps single blank lines between statements, and drops the rest
+///
+/// Formatting is idempotent: formatting the output again doesn't change it.
+pub fn format_config(input: &str) -> Result<String, Error> {
+    let mut tree = parse_lossless(input);
+
+    if !tree.errors.is_empty() {
+        return Err(tree.errors.remove(0));
+    }
+
+    let mut formatter = Formatter {
+        source: input,
+        lines: vec![],
+        line: None,
+        depth: 0,
+        newlines: 0,
+        break_pending: false,
+        prev_end: None,
+    };
+
+    formatter.elements(&tree.elements);
+    formatter.end_line();
+
+    Ok(formatter.finish())
+}
+
+/// A line of output.
+struct Line {
+    depth: usize,
+    code: String,
+    comment: Option<String>,
+    /// For a line holding a `map` statement, where its keys end and its command begins, so that
+    /// the commands of neighbouring lines can be lined up.
+    binding: Option<usize>,
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    lines: Vec<Line>,
+    /// The line being written, if it's been started.
+    line: Option<Line>,
+    /// How many `if` blocks the next line starts in.
+    depth: usize,
+    /// The number of newlines in the source since the last line ended, to tell blank lines apart.
+    newlines: usize,
+    /// Set after a `{`, whose line ends before anything but a comment is written.
+    break_pending: bool,
+    /// Where the last word written ended in the source, to tell whether the next one touched it.
+    prev_end: Option<usize>,
+}
+
+impl Formatter<'_> {
+    fn elements(&mut self, elements: &[SyntaxElement]) {
+        for element in elements {
+            let token = match element {
+                SyntaxElement::Statement(node) => {
+                    self.statement(node);
+                    continue;
+                }
+                SyntaxElement::Token(token) => token,
+            };
+
+            match token.kind() {
+                TokenKind::Whitespace => (),
+                TokenKind::Newline => {
+                    if self.line.is_some() {
+                        self.end_line();
+                    }
+
+                    self.newlines += 1;
+                    self.break_pending = false;
+                }
+                TokenKind::Comment(_) => {
+                    let comment = token.source_text(self.source).trim_end().to_string();
+
+                    match &mut self.line {
+                        Some(line) => line.comment = Some(comment),
+                        None => self.start().comment = Some(comment),
+                    }
+                }
+                TokenKind::Phrase("{") => {
+                    self.write(token.span(), "{", true);
+                    self.depth += 1;
+                    self.break_pending = true;
+                }
+                TokenKind::Phrase("}") => {
+                    // A `}` always starts a line of its own, straight after the block's last line.
+                    self.end_line();
+                    self.newlines = 0;
+                    self.break_pending = false;
+                    self.depth = self.depth.saturating_sub(1);
+                    self.write(token.span(), "}", true);
+                }
+                TokenKind::Phrase("else") => self.write(token.span(), "else", true),
+                _ => {
+                    let touching = self.prev_end == Some(token.span().start.offset);
+                    let text = token.source_text(self.source);
+
+                    self.write(token.span(), text, !touching);
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self, node: &StatementNode) {
+        let (text, binding) = match node.statement() {
+            Statement::If(_) => {
+                self.elements(node.elements());
+                return;
+            }
+            Statement::Map(map) => {
+                let (binding, command) = self.map(node, map);
+
+                (format!("{} {}", binding, command), Some(binding.len()))
+            }
+            Statement::Unmap(unmap) => (self.unmap(node, unmap), None),
+            _ => (self.join(&node.tokens()), None),
+        };
+
+        let is_new_line = self.start().code.is_empty();
+
+        self.write(node.span(), &text, true);
+
+        if is_new_line {
+            if let Some(line) = &mut self.line {
+                line.binding = binding;
+            }
+        }
+    }
+
+    /// Returns the canonical `map` and keys of a `map` statement, and its command.
+    fn map(&self, node: &StatementNode, map: &Map) -> (String, String) {
+        let tokens = words(node);
+        let command_span = map.command().span();
+        let head = self.head("map", &tokens, map.mode());
+
+        let command = self.join(&tokens_in(&tokens, command_span.start.offset, usize::MAX));
+        let keys = fmt_keys(map.keys());
+
+        let is_same = |statement: Statement| match statement {
+            Statement::Map(formatted) => {
+                formatted.keys == map.keys
+                    && formatted.command.to_string() == map.command.to_string()
+            }
+            _ => false,
+        };
+
+        if reparses(&format!("{} {} {}", head, keys, command), is_same) {
+            return (format!("{} {}", head, keys), command);
+        }
+
+        // Some sequences read differently once they're spelled out, such as `a\ b` in
+        // `map a\ b x`, which would bind `a` to `space b x`. Those are kept as they were written.
+        let keys_start = tokens[self.head_len(&tokens)].span().start.offset;
+        let keys = self.join(&tokens_in(&tokens, keys_start, command_span.start.offset));
+
+        (format!("{} {}", head, keys), command)
+    }
+
+    /// Returns the canonical text of an `unmap` statement.
+    fn unmap(&self, node: &StatementNode, unmap: &Unmap) -> String {
+        let tokens = words(node);
+        let head = self.head("unmap", &tokens, unmap.mode());
+
+        let mut text = head.clone();
+
+        if !unmap.keys().is_empty() {
+            text = format!("{} {}", text, fmt_keys(unmap.keys()));
+        }
+
+        if unmap.is_prefix() {
+            text.push_str(" *");
+        }
+
+        let is_same = |statement: Statement| match statement {
+            Statement::Unmap(formatted) => {
+                formatted.keys == unmap.keys && formatted.is_prefix == unmap.is_prefix
+            }
+            _ => false,
+        };
+
+        if reparses(&text, is_same) {
+            return text;
+        }
+
+        format!("{} {}", head, self.join(&tokens[self.head_len(&tokens)..]))
+    }
+
+    /// Returns the keyword of a `map` or `unmap` statement, with the canonical name of its mode
+    /// if it names one.
+    fn head(&self, keyword: &str, tokens: &[&Token], mode: Mode) -> String {
+        if self.head_len(tokens) > 1 {
+            format!("{}[{}]", keyword, mode)
+        } else {
+            keyword.to_string()
+        }
+    }
+
+    /// Returns the number of tokens taken up by the keyword of a `map` or `unmap` statement and
+    /// the mode after it.
+    fn head_len(&self, tokens: &[&Token]) -> usize {
+        match tokens.get(1) {
+            Some(token) if token.kind() == &TokenKind::Phrase("[") => 4,
+            _ => 1,
+        }
+    }
+
+    /// Joins the text of `tokens`, with a space between the ones that weren't touching.
+    fn join(&self, tokens: &[&Token]) -> String {
+        let mut text = String::new();
+        let mut prev: Option<&Token> = None;
+
+        for &token in tokens.iter().filter(|token| !token.is_trivia()) {
+            let is_separator = |token: &Token| token.kind() == &TokenKind::Phrase(";");
+
+            // The `;` between the commands of a `cmd` is written as `; `, however it was spaced.
+            let space = match prev {
+                None => false,
+                Some(_) if is_separator(token) => false,
+                Some(prev) if is_separator(prev) => true,
+                Some(prev) => prev.span().end.offset != token.span().start.offset,
+            };
+
+            if space {
+                text.push(' ');
+            }
+
+            text.push_str(token.source_text(self.source));
+            prev = Some(token);
+        }
+
+        text
+    }
+
+    /// Returns the line being written, starting one if there isn't one.
+    fn start(&mut self) -> &mut Line {
+        if self.break_pending {
+            self.end_line();
+            self.break_pending = false;
+        }
+
+        if self.line.is_none() {
+            let follows_blank = self.newlines > 1;
+            let opens_block = self
+                .lines
+                .last()
+                .is_none_or(|line| line.code.ends_with('{'));
+
+            if follows_blank && !opens_block {
+                self.lines.push(Line {
+                    depth: 0,
+                    code: String::new(),
+                    comment: None,
+                    binding: None,
+                });
+            }
+
+            self.newlines = 0;
+        }
+
+        let depth = self.depth;
+
+        self.line.get_or_insert_with(|| Line {
+            depth,
+            code: String::new(),
+            comment: None,
+            binding: None,
+        })
+    }
+
+    /// Writes `text`, which was parsed from `span`, to the line, after a space if `space` is set
+    /// and the line already has something on it.
+    fn write(&mut self, span: Span, text: &str, space: bool) {
+        let line = self.start();
+
+        if space && !line.code.is_empty() {
+            line.code.push(' ');
+        }
+
+        line.code.push_str(text);
+        self.prev_end = Some(span.end.offset);
+    }
+
+    fn end_line(&mut self) {
+        if let Some(line) = self.line.take() {
+            self.lines.push(line);
+            self.newlines = 0;
+        }
+    }
+
+    fn finish(mut self) -> String {
+        align_bindings(&mut self.lines);
+
+        let mut output = String::new();
+
+        for line in &self.lines {
+            if !line.code.is_empty() || line.comment.is_some() {
+                output.push_str(&INDENT.repeat(line.depth));
+            }
+
+            output.push_str(&line.code);
+
+            if let Some(comment) = &line.comment {
+                if !line.code.is_empty() {
+                    output.push(' ');
+                }
+
+                output.push_str(comment);
+            }
+
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+/// Pads the keys of each run of consecutive `map` lines at the same depth, so that their commands
+/// start in the same column.
+fn align_bindings(lines: &mut [Line]) {
+    let mut start = 0;
+
+    while start < lines.len() {
+        let depth = lines[start].depth;
+        let len = lines[start..]
+            .iter()
+            .take_while(|line| line.binding.is_some() && line.depth == depth)
+            .count();
+
+        let run = &mut lines[start..start + len.max(1)];
+        let width = run
+            .iter()
+            .filter_map(|line| Some(line.code[..line.binding?].chars().count()))
+            .max()
+            .unwrap_or(0);
+
+        for line in run.iter_mut() {
+            if let Some(binding) = line.binding {
+                let padding = width - line.code[..binding].chars().count();
+
+                line.code.insert_str(binding, &" ".repeat(padding));
+            }
+        }
+
+        start += len.max(1);
+    }
+}
+
+/// Returns the tokens of a statement other than its whitespace.
+fn words(node: &StatementNode) -> Vec<&Token> {
+    let mut tokens = node.tokens();
+    tokens.retain(|token| !token.is_trivia());
+
+    tokens
+}
+
+/// Returns the tokens that lie between the byte offsets `start` and `end`.
+fn tokens_in<'a>(tokens: &[&'a Token], start: usize, end: usize) -> Vec<&'a Token> {
+    tokens
+        .iter()
+        .filter(|token| token.span().start.offset >= start && token.span().end.offset <= end)
+        .copied()
+        .collect()
+}
+
+/// Returns true if `text` parses as a single statement that `is_same` accepts, so that a
+/// canonical spelling is only used where it means the same as the original.
+fn reparses(text: &str, is_same: impl Fn(Statement) -> bool) -> bool {
+    match parse_config(text) {
+        Ok(program) if program.len() == 1 => program.into_iter().next().is_some_and(is_same),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: &[&str] = &[
+        "",
+        "map j down\n",
+        "\n\n# Navigation\nmap   j   down   # move down\nmap shift+ctrl+k    up\nmap gg top\n\
+         map G   bottom\nmap[selection]   d delete\nmap gd x y\n\n\n\nmap Return open\n",
+        "set   ratios  [1 2 3]\nunmap  g *\nunmap[visual] *\nunmap \\*\n",
+        "if os   linux{ # linux only\n    set hidden true\n\n\n   map  ctrl+Home top\n\
+         }else if env X {map k up} else {\n}\n",
+        "cmd trash 'mv \"$f\" ~/.trash'\ncmd foo top ;bottom\nlet dir  =  \"$HOME/x\"   # where\n",
+        "map a\\ b x\nmap * x\nmap ctrl+= x\nmap g = x\n",
+        "if os linux {\n    if env X {\n        map k up\n    }\n\n    # comment\n}\n",
+    ];
+
+    /// Removes every span from the debug output of a program, so that programs can be compared
+    /// by what they mean rather than where they were written.
+    fn without_spans(debug: &str) -> String {
+        let mut text = String::new();
+        let mut rest = debug;
+
+        while let Some(start) = rest.find("Span {") {
+            text.push_str(&rest[..start]);
+
+            let mut depth = 0;
+            let mut end = start;
+
+            for (index, ch) in rest[start..].char_indices() {
+                match ch {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => continue,
+                }
+
+                if depth == 0 {
+                    end = start + index + 1;
+                    break;
+                }
+            }
+
+            rest = &rest[end..];
+        }
+
+        text.push_str(rest);
+        text
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for input in INPUTS {
+            let once = format_config(input).unwrap();
+            let twice = format_config(&once).unwrap();
+
+            assert_eq!(once, twice, "formatting {:?}", input);
+        }
+    }
+
+    #[test]
+    fn formatting_keeps_the_meaning() {
+        for input in INPUTS {
+            let before = parse_config(input).unwrap();
+            let after = parse_config(&format_config(input).unwrap()).unwrap();
+
+            assert_eq!(
+                without_spans(&format!("{:?}", before)),
+                without_spans(&format!("{:?}", after)),
+                "formatting {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn formatting_keeps_comments() {
+        for input in INPUTS {
+            let output = format_config(input).unwrap();
+            let comments = |text: &str| -> Vec<String> {
+                parse_lossless(text)
+                    .tokens()
+                    .iter()
+                    .filter(|token| matches!(token.kind(), TokenKind::Comment(_)))
+                    .map(|token| token.source_text(text).trim_end().to_string())
+                    .collect()
+            };
+
+            assert_eq!(comments(input), comments(&output));
+        }
+    }
+
+    #[test]
+    fn canonical_style() {
+        let input = "map   j   down   # move\nmap shift+ctrl+k    up\nmap gg top\n\n\n\
+                     if os linux{set hidden true}\ncmd foo top ;bottom\n";
+        let expected =
+            "map j            down # move\nmap ctrl+shift+k up\nmap g g          top\n\n\
+                        if os linux {\n    set hidden true\n}\ncmd foo top; bottom\n";
+
+        assert_eq!(format_config(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn keeps_sequences_that_would_change_meaning() {
+        assert_eq!(format_config("map a\\ b  x\n").unwrap(), "map a\\ b x\n");
+    }
+
+    #[test]
+    fn refuses_files_with_errors() {
+        assert!(format_config("map j down\nmap\n").is_err());
+    }
+}
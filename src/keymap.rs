@@ -0,0 +1,259 @@
+//! Lookup of the command bound to a sequence of keys in a mode.
+
+use std::collections::HashMap;
+
+use crate::ast::{Command, Key, Mode, Program, Statement};
+
+/// A trie of key sequences for each mode, built from the `map` and `unmap` statements in a program.
+/// Each mode's bindings are separate, so a key that is only bound in normal mode does nothing in
+/// visual mode.
+///
+/// A sequence is either bound to a command or is a prefix of longer bindings, never both. When
+/// one binding conflicts with an earlier one, the later binding wins: binding `g g` removes a
+/// binding for `g`, and binding `g` removes every binding that starts with `g`.
+#[derive(Debug, Default, Clone)]
+pub struct Keymap {
+    modes: HashMap<Mode, Node>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Node {
+    command: Option<Command>,
+    children: HashMap<Key, Node>,
+}
+
+/// The result of looking up a sequence of keys in a `Keymap`.
+#[derive(Debug, Clone, Copy)]
+pub enum Match<'a> {
+    /// The keys are bound to this command.
+    Command(&'a Command),
+    /// The keys are the start of at least one binding, so more keys are needed.
+    Prefix,
+    /// Nothing is bound to the keys or to any sequence that starts with them.
+    None,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a keymap from the `map` and `unmap` statements in `program`, in order. `if` blocks
+    /// are skipped, so a program with them should be evaluated with a
+    /// [`Context`](crate::Context) first.
+    pub fn from_program(program: &Program) -> Self {
+        let mut keymap = Self::new();
+
+        for statement in program {
+            match statement {
+                Statement::Map(map) => keymap.insert(map.mode(), map.keys(), map.command().clone()),
+                Statement::Unmap(unmap) if unmap.is_prefix() => {
+                    keymap.remove_prefix(unmap.mode(), unmap.keys())
+                }
+                Statement::Unmap(unmap) => keymap.remove(unmap.mode(), unmap.keys()),
+                // Only `map` and `unmap` statements affect bindings.
+                _ => (),
+            }
+        }
+
+        keymap
+    }
+
+    /// Binds `keys` to `command` in `mode`, replacing any bindings in that mode that conflict with
+    /// it.
+    ///
+    /// Binding an empty sequence does nothing.
+    pub fn insert(&mut self, mode: Mode, keys: &[Key], command: Command) {
+        if keys.is_empty() {
+            return;
+        }
+
+        let mut node = self.modes.entry(mode).or_default();
+
+        for key in keys {
+            // A prefix of the new binding can't be bound itself.
+            node.command = None;
+            node = node.children.entry(key.clone()).or_default();
+        }
+
+        node.children.clear();
+        node.command = Some(command);
+    }
+
+    /// Removes the binding for exactly `keys` in `mode`, if there is one. Bindings that only start
+    /// with `keys` are kept.
+    pub fn remove(&mut self, mode: Mode, keys: &[Key]) {
+        if let Some(root) = self.modes.get_mut(&mode) {
+            root.remove(keys, false);
+        }
+    }
+
+    /// Removes every binding in `mode` that starts with `keys`, including one for `keys` itself.
+    /// With no keys, this removes every binding in the mode.
+    pub fn remove_prefix(&mut self, mode: Mode, keys: &[Key]) {
+        if let Some(root) = self.modes.get_mut(&mode) {
+            root.remove(keys, true);
+        }
+    }
+
+    /// Looks up what `keys` do in `mode`. The empty sequence is a prefix of every binding.
+    pub fn lookup(&self, mode: Mode, keys: &[Key]) -> Match<'_> {
+        let Some(mut node) = self.modes.get(&mode) else {
+            return Match::None;
+        };
+
+        for key in keys {
+            match node.children.get(key) {
+                Some(child) => node = child,
+                None => return Match::None,
+            }
+        }
+
+        match &node.command {
+            Some(command) => Match::Command(command),
+            None if !node.children.is_empty() => Match::Prefix,
+            None => Match::None,
+        }
+    }
+}
+
+impl From<&Program> for Keymap {
+    fn from(program: &Program) -> Self {
+        Self::from_program(program)
+    }
+}
+
+impl Node {
+    /// Removes the binding for `keys` under this node, along with every binding that starts with
+    /// them if `is_prefix` is set. Nodes left without bindings are pruned, so that they aren't
+    /// reported as prefixes.
+    fn remove(&mut self, keys: &[Key], is_prefix: bool) {
+        match keys.split_first() {
+            Some((first, rest)) => {
+                if let Some(child) = self.children.get_mut(first) {
+                    child.remove(rest, is_prefix);
+
+                    if child.command.is_none() && child.children.is_empty() {
+                        self.children.remove(first);
+                    }
+                }
+            }
+            None => {
+                self.command = None;
+
+                if is_prefix {
+                    self.children.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_config, KeyCode, Mods};
+
+    fn keymap(input: &str) -> Keymap {
+        Keymap::from_program(&parse_config(input).unwrap())
+    }
+
+    /// Looks up `keys` in normal mode, written as they would be in a `map` statement, and
+    /// describes the result: the name of the command, "prefix" or "none".
+    fn lookup(keymap: &Keymap, keys: &str) -> String {
+        let keys = match keys {
+            "" => vec![],
+            keys => match &parse_config(&format!("map {} x", keys)).unwrap()[0] {
+                Statement::Map(map) => map.keys().to_vec(),
+                _ => unreachable!(),
+            },
+        };
+
+        match keymap.lookup(Mode::Normal, &keys) {
+            Match::Command(command) => command.name().to_string(),
+            Match::Prefix => "prefix".to_string(),
+            Match::None => "none".to_string(),
+        }
+    }
+
+    #[test]
+    fn looks_up_commands_and_prefixes() {
+        let keymap = keymap("map j down\nmap g g top\nmap g e bottom\nmap ctrl+k up\n");
+
+        assert_eq!(lookup(&keymap, "j"), "down");
+        assert_eq!(lookup(&keymap, "g"), "prefix");
+        assert_eq!(lookup(&keymap, "g g"), "top");
+        assert_eq!(lookup(&keymap, "g e"), "bottom");
+        assert_eq!(lookup(&keymap, "ctrl+k"), "up");
+        assert_eq!(lookup(&keymap, "k"), "none");
+        assert_eq!(lookup(&keymap, "j j"), "none");
+        assert_eq!(lookup(&keymap, ""), "prefix");
+    }
+
+    #[test]
+    fn later_bindings_override_earlier_ones() {
+        let keymap = keymap("map j down\nmap j up\n");
+
+        assert_eq!(lookup(&keymap, "j"), "up");
+    }
+
+    #[test]
+    fn binding_a_sequence_clears_its_prefix() {
+        let keymap = keymap("map g top\nmap g g top\n");
+
+        assert_eq!(lookup(&keymap, "g"), "prefix");
+        assert_eq!(lookup(&keymap, "g g"), "top");
+    }
+
+    #[test]
+    fn binding_a_prefix_clears_its_sequences() {
+        let keymap = keymap("map g g top\nmap g e bottom\nmap g go\n");
+
+        assert_eq!(lookup(&keymap, "g"), "go");
+        assert_eq!(lookup(&keymap, "g g"), "none");
+        assert_eq!(lookup(&keymap, "g e"), "none");
+    }
+
+    #[test]
+    fn unmap_removes_exactly_its_keys() {
+        let keymap = keymap("map g top\nmap g g top\nmap j down\nunmap g\nunmap j\n");
+
+        assert_eq!(lookup(&keymap, "g"), "prefix");
+        assert_eq!(lookup(&keymap, "g g"), "top");
+        assert_eq!(lookup(&keymap, "j"), "none");
+        assert_eq!(lookup(&keymap, ""), "prefix");
+    }
+
+    #[test]
+    fn unmap_with_a_star_removes_every_binding_under_its_keys() {
+        let keymap = keymap("map g g top\nmap g e bottom\nmap j down\nunmap g *\n");
+
+        assert_eq!(lookup(&keymap, "g"), "none");
+        assert_eq!(lookup(&keymap, "g g"), "none");
+        assert_eq!(lookup(&keymap, "j"), "down");
+    }
+
+    #[test]
+    fn unmap_star_removes_every_binding() {
+        let keymap = keymap("map g g top\nmap j down\nunmap *\nmap k up\n");
+
+        assert_eq!(lookup(&keymap, "g"), "none");
+        assert_eq!(lookup(&keymap, "j"), "none");
+        assert_eq!(lookup(&keymap, "k"), "up");
+    }
+
+    #[test]
+    fn modes_have_separate_bindings() {
+        let keymap = keymap("map j down\nmap[visual] j up\nunmap[visual] *\nmap[visual] k up\n");
+
+        assert_eq!(lookup(&keymap, "j"), "down");
+        assert!(matches!(
+            keymap.lookup(Mode::Visual, &[Key::new(Mods::NONE, KeyCode::Char('j'))]),
+            Match::None
+        ));
+        assert!(matches!(
+            keymap.lookup(Mode::Visual, &[Key::new(Mods::NONE, KeyCode::Char('k'))]),
+            Match::Command(_)
+        ));
+    }
+}
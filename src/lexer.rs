@@ -0,0 +1,1064 @@
+use core::fmt;
+use std::{error::Error, mem, ops};
+
+use crate::{
+    ast::{Fallback, Mod, Template, TemplatePart},
+    diagnostic::{Diagnostic, Position},
+    span::{FileId, Location, Span, Spanned},
+};
+
+pub type LexResult<T> = std::result::Result<T, LexError>;
+
+type Lexer = dyn Fn(&mut Scanner) -> LexResult<Token>;
+
+/// Lexes the whole input, stopping at the first error.
+pub fn lex(scanner: &mut Scanner) -> LexResult<Vec<Token>> {
+    let (tokens, errors) = lex_with_errors(scanner);
+
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(tokens),
+    }
+}
+
+/// Lexes the whole input, reporting every error. Each character that can't be lexed becomes a
+/// `TokenKind::Error` token, so that the parser can skip over it and keep going.
+pub fn lex_with_errors(scanner: &mut Scanner) -> (Vec<Token>, Vec<LexError>) {
+    lex_tokens(scanner, false)
+}
+
+/// Like [`lex_with_errors`], but keeps the whitespace tokens, so that every byte of the input
+/// belongs to exactly one token. Concatenating the text of the tokens gives back the input.
+pub fn lex_lossless(scanner: &mut Scanner) -> (Vec<Token>, Vec<LexError>) {
+    lex_tokens(scanner, true)
+}
+
+fn lex_tokens(scanner: &mut Scanner, keep_whitespace: bool) -> (Vec<Token>, Vec<LexError>) {
+    let lex_map = lex_keyword("map");
+    let lex_unmap = lex_keyword("unmap");
+    let lex_set = lex_keyword("set");
+    let lex_cmd = lex_keyword("cmd");
+    let lex_source = lex_keyword("source");
+    let lex_if = lex_keyword("if");
+    let lex_else = lex_keyword("else");
+    let lex_let = lex_keyword("let");
+    let lex_plus = lex_phrase("+");
+    let lex_open_bracket = lex_phrase("[");
+    let lex_close_bracket = lex_phrase("]");
+    let lex_semicolon = lex_phrase(";");
+    let lex_open_brace = lex_phrase("{");
+    let lex_close_brace = lex_phrase("}");
+    // `*` and `=` are only special on their own, so `*.txt` and `--sort=size` are still words.
+    let lex_star = lex_keyword("*");
+    let lex_equals = lex_keyword("=");
+
+    // NOTE(Chris): The order matters here, in case one lexing rule conflicts with another.
+    let mut lexers: Vec<&Lexer> = vec![
+        &lex_comment,
+        &lex_string,
+        &lex_mod,
+        &lex_newline,
+        &lex_whitespace,
+        &*lex_map,
+        &*lex_unmap,
+        &*lex_set,
+        &*lex_cmd,
+        &*lex_source,
+        &*lex_if,
+        &*lex_else,
+        &*lex_let,
+        &*lex_plus,
+        &*lex_open_bracket,
+        &*lex_close_bracket,
+        &*lex_semicolon,
+        &*lex_open_brace,
+        &*lex_close_brace,
+        &*lex_star,
+        &*lex_equals,
+    ];
+
+    lexers.push(&lex_word);
+
+    let mut tokens = vec![];
+    let mut errors = vec![];
+
+    'scanner: while !scanner.is_done() {
+        let start = scanner.location();
+
+        for lexer in &lexers {
+            match lexer(scanner) {
+                Ok(mut token) => {
+                    // Lexers create their tokens once they've consumed them, so move the start of
+                    // the span "back" to where the token began.
+                    token.span.start = start;
+
+                    if keep_whitespace || token.kind != TokenKind::Whitespace {
+                        tokens.push(token);
+                    }
+
+                    continue 'scanner;
+                }
+                // A lexer that consumed input before failing has recognized the start of its
+                // token, so its error is a real one rather than a sign to try the next lexer.
+                Err(err) if scanner.location() != start => {
+                    errors.push(err);
+
+                    tokens.push(Token {
+                        span: scanner.span_from(start),
+                        kind: TokenKind::Error,
+                    });
+
+                    continue 'scanner;
+                }
+                Err(_) => (),
+            }
+        }
+
+        let unexpected = *scanner.peek().unwrap();
+
+        errors.push(LexError::new(
+            scanner,
+            LexErrorKind::UnexpectedChar(unexpected),
+        ));
+
+        scanner.pop();
+
+        tokens.push(Token {
+            span: scanner.span_from(start),
+            kind: TokenKind::Error,
+        });
+    }
+
+    (tokens, errors)
+}
+
+/// Characters with a meaning of their own in the grammar. To use one of these in a bare word, such
+/// as binding the `+` key, escape it with a backslash.
+pub(crate) const SPECIAL_CHARS: &[char] = &['+', '#', '"', '\'', '\\', '[', ']', ';', '{', '}'];
+
+/// Returns true for the characters that can make up a bare word, such as an identifier, a number,
+/// a path like `~/Downloads` or a punctuation key like `?`.
+pub(crate) fn is_word_char(ch: char) -> bool {
+    !ch.is_whitespace() && !ch.is_control() && !SPECIAL_CHARS.contains(&ch)
+}
+
+/// Lexes a bare word, in which any character can be escaped with a backslash. Words made up
+/// entirely of digits (with an optional leading `-`) are numbers, words with variable references
+/// in them are templates, and everything else is an identifier.
+fn lex_word(scanner: &mut Scanner) -> LexResult<Token> {
+    let start = scanner.location();
+    let mut buf = String::new();
+    let mut parts = vec![];
+    let mut has_escapes = false;
+
+    loop {
+        match scanner.peek() {
+            Some('$') => match lex_variable(scanner)? {
+                Some(var) => push_variable(&mut parts, &mut buf, var),
+                None => {
+                    buf.push('$');
+                    scanner.pop();
+                }
+            },
+            Some(&ch) if is_word_char(ch) => {
+                buf.push(ch);
+                scanner.pop();
+            }
+            Some('\\') => {
+                let escape_start = scanner.location();
+
+                scanner.pop();
+
+                match scanner.peek() {
+                    Some(&ch) if ch != '\n' => {
+                        buf.push(ch);
+                        scanner.pop();
+                    }
+                    _ => {
+                        return Err(LexError::new_span(
+                            scanner.span_from(escape_start),
+                            LexErrorKind::ExpectedEscapedChar,
+                        ))
+                    }
+                }
+
+                has_escapes = true;
+            }
+            _ => break,
+        }
+    }
+
+    if !parts.is_empty() {
+        return Ok(Token::new(
+            scanner,
+            TokenKind::Template(finish_template(parts, buf)),
+        ));
+    }
+
+    if buf.is_empty() {
+        return Err(LexError::new(scanner, LexErrorKind::ExpectedId));
+    }
+
+    let digits = buf.strip_prefix('-').unwrap_or(&buf);
+    let is_number =
+        !has_escapes && !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_digit());
+
+    if !is_number {
+        return Ok(Token::new(scanner, TokenKind::Id(buf)));
+    }
+
+    match buf.parse::<i64>() {
+        // Words like `007` aren't written the way the number would be, so keep them as they are.
+        Ok(num) if num.to_string() != buf => Ok(Token::new(scanner, TokenKind::Id(buf))),
+        Ok(num) => Ok(Token::new(scanner, TokenKind::Num(num))),
+        Err(_) => Err(LexError::new_span(
+            scanner.span_from(start),
+            LexErrorKind::NumberTooLarge,
+        )),
+    }
+}
+
+/// Lexes a `$name`, `${name}`, `${name:-default}` or `${name:?message}` variable reference,
+/// starting at its `$`. Names are made up of letters, digits and underscores, and can't start with
+/// a digit. Returns None without consuming anything if the `$` doesn't start a reference, as with
+/// the `$` key or `$1`, so that it's kept as a plain `$`.
+fn lex_variable(scanner: &mut Scanner) -> LexResult<Option<TemplatePart>> {
+    let start = scanner.location();
+    let is_name_start = |ch: Option<&char>| ch.is_some_and(|ch| ch.is_alphabetic() || *ch == '_');
+
+    let (name, fallback) = match scanner.peek_nth(1) {
+        Some('{') => {
+            scanner.pop();
+            scanner.pop();
+
+            if !is_name_start(scanner.peek()) {
+                return Err(LexError::new(scanner, LexErrorKind::ExpectedVariableName));
+            }
+
+            let name = lex_variable_name(scanner);
+
+            let fallback = if scanner.take_str(":-") {
+                Some(Fallback::Default(lex_fallback(scanner, true)?))
+            } else if scanner.take_str(":?") {
+                let message = lex_fallback(scanner, false)?;
+
+                Some(Fallback::Error(message.to_literal()))
+            } else {
+                None
+            };
+
+            if !scanner.take(&'}') {
+                return Err(LexError::new_span(
+                    scanner.span_from(start),
+                    LexErrorKind::UnclosedVariable,
+                ));
+            }
+
+            (name, fallback)
+        }
+        ch if is_name_start(ch) => {
+            scanner.pop();
+
+            (lex_variable_name(scanner), None)
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(TemplatePart::Var {
+        name,
+        fallback,
+        span: scanner.span_from(start),
+    }))
+}
+
+/// Lexes the text after the `:-` or `:?` of a reference, up to the `}` that closes it, which is
+/// left for the caller. A backslash escapes the character after it, such as a `}`. References in
+/// the text are only lexed if `has_variables` is set.
+fn lex_fallback(scanner: &mut Scanner, has_variables: bool) -> LexResult<Template> {
+    let mut buf = String::new();
+    let mut parts = vec![];
+
+    loop {
+        match scanner.peek() {
+            Some('}') => break,
+            Some('$') if has_variables => match lex_variable(scanner)? {
+                Some(var) => push_variable(&mut parts, &mut buf, var),
+                None => {
+                    buf.push('$');
+                    scanner.pop();
+                }
+            },
+            Some('\\') => {
+                scanner.pop();
+
+                match scanner.peek() {
+                    Some(&ch) if ch != '\n' => {
+                        buf.push(ch);
+                        scanner.pop();
+                    }
+                    _ => break,
+                }
+            }
+            Some('\n') | None => break,
+            Some(&ch) => {
+                buf.push(ch);
+                scanner.pop();
+            }
+        }
+    }
+
+    // A missing `}` is reported by the caller.
+    Ok(finish_template(parts, buf))
+}
+
+fn lex_variable_name(scanner: &mut Scanner) -> String {
+    let mut name = String::new();
+
+    while let Some(ch) = scanner.transform(|ch| (ch.is_alphanumeric() || *ch == '_').then_some(*ch))
+    {
+        name.push(ch);
+    }
+
+    name
+}
+
+/// Adds a variable reference to the parts of a template, after the text before it.
+fn push_variable(parts: &mut Vec<TemplatePart>, buf: &mut String, var: TemplatePart) {
+    if !buf.is_empty() {
+        parts.push(TemplatePart::Text(mem::take(buf)));
+    }
+
+    parts.push(var);
+}
+
+/// Makes a template out of the parts lexed so far and the text after the last of them.
+fn finish_template(mut parts: Vec<TemplatePart>, buf: String) -> Template {
+    if !buf.is_empty() {
+        parts.push(TemplatePart::Text(buf));
+    }
+
+    Template { parts }
+}
+
+fn lex_mod(scanner: &mut Scanner) -> LexResult<Token> {
+    if scanner.take_word("ctrl") {
+        Ok(Token::new(scanner, TokenKind::Mod(Mod::Ctrl)))
+    } else if scanner.take_word("shift") {
+        Ok(Token::new(scanner, TokenKind::Mod(Mod::Shift)))
+    } else if scanner.take_word("alt") {
+        Ok(Token::new(scanner, TokenKind::Mod(Mod::Alt)))
+    } else {
+        Err(LexError::new(scanner, LexErrorKind::ExpectedMod))
+    }
+}
+
+/// Lexes a keyword, which has to be a whole word so that e.g. `mapping` isn't lexed as `map`
+/// followed by `ping`.
+fn lex_keyword(keyword: &'static str) -> Box<Lexer> {
+    Box::new(move |scanner: &mut Scanner| {
+        if scanner.take_word(keyword) {
+            Ok(Token::new(scanner, TokenKind::Phrase(keyword)))
+        } else {
+            Err(LexError::new(
+                scanner,
+                LexErrorKind::ExpectedPhrase(keyword),
+            ))
+        }
+    })
+}
+
+fn lex_phrase(phrase: &'static str) -> Box<Lexer> {
+    Box::new(move |scanner: &mut Scanner| {
+        if scanner.take_str(phrase) {
+            Ok(Token::new(scanner, TokenKind::Phrase(phrase)))
+        } else {
+            Err(LexError::new(scanner, LexErrorKind::ExpectedPhrase(phrase)))
+        }
+    })
+}
+
+fn lex_whitespace(scanner: &mut Scanner) -> LexResult<Token> {
+    let mut was_whitespace = false;
+
+    while let Some(_ch) = scanner.pop_in_slice(&[' ', '\t']) {
+        was_whitespace = true;
+    }
+
+    if was_whitespace {
+        Ok(Token::new(scanner, TokenKind::Whitespace))
+    } else {
+        Err(LexError::new(scanner, LexErrorKind::ExpectedWhitespace))
+    }
+}
+
+/// Lexes a `#` comment, which runs until the end of the line.
+fn lex_comment(scanner: &mut Scanner) -> LexResult<Token> {
+    if !scanner.take(&'#') {
+        return Err(LexError::new(scanner, LexErrorKind::Expected('#')));
+    }
+
+    let mut text = String::new();
+
+    while let Some(&ch) = scanner.peek() {
+        if ch == '\n' {
+            break;
+        }
+
+        text.push(ch);
+        scanner.pop();
+    }
+
+    Ok(Token::new(scanner, TokenKind::Comment(text)))
+}
+
+/// Lexes a single- or double-quoted string, which may not span multiple lines. Variable references
+/// in a double-quoted string make it a template, while a single-quoted string is always taken
+/// literally.
+///
+/// Supports the escapes `\n`, `\t`, `\"`, `\'`, `\\`, `\$` and `\u{...}`. After an invalid
+/// escape, the rest of the string is still consumed so that lexing can carry on after it.
+fn lex_string(scanner: &mut Scanner) -> LexResult<Token> {
+    let start = scanner.location();
+
+    let quote = match scanner.pop_in_slice(&['"', '\'']) {
+        Some(quote) => quote,
+        None => return Err(LexError::new(scanner, LexErrorKind::ExpectedString)),
+    };
+
+    let mut text = String::new();
+    let mut parts = vec![];
+    let mut first_error = None;
+
+    loop {
+        match scanner.peek() {
+            Some(&ch) if ch == quote => {
+                scanner.pop();
+                break;
+            }
+            Some('$') if quote == '"' => match lex_variable(scanner) {
+                Ok(Some(var)) => push_variable(&mut parts, &mut text, var),
+                Ok(None) => {
+                    text.push('$');
+                    scanner.pop();
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            },
+            Some('\\') => match lex_escape(scanner) {
+                Ok(ch) => text.push(ch),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            },
+            Some('\n') | None => {
+                return Err(LexError::new_span(
+                    scanner.span_from(start),
+                    LexErrorKind::UnterminatedString,
+                ))
+            }
+            Some(&ch) => {
+                text.push(ch);
+                scanner.pop();
+            }
+        }
+    }
+
+    match first_error {
+        Some(err) => Err(err),
+        None if !parts.is_empty() => Ok(Token::new(
+            scanner,
+            TokenKind::Template(finish_template(parts, text)),
+        )),
+        None => Ok(Token::new(scanner, TokenKind::Str(text))),
+    }
+}
+
+/// Lexes an escape sequence in a string, starting at its backslash. Returns the escaped
+/// character.
+fn lex_escape(scanner: &mut Scanner) -> LexResult<char> {
+    let start = scanner.location();
+
+    scanner.expect(&'\\')?;
+
+    // Leave a newline for the string to report as unterminated.
+    let ch = match scanner.peek() {
+        Some('\n') | None => return Err(LexError::new(scanner, LexErrorKind::UnterminatedString)),
+        Some(&ch) => ch,
+    };
+
+    scanner.pop();
+
+    match ch {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        '"' => Ok('"'),
+        '\'' => Ok('\''),
+        '\\' => Ok('\\'),
+        '$' => Ok('$'),
+        'u' => lex_unicode_escape(scanner, start),
+        other => Err(LexError::new_span(
+            scanner.span_from(start),
+            LexErrorKind::InvalidEscape(other),
+        )),
+    }
+}
+
+/// Lexes the `{...}` part of a `\u{...}` escape, which holds 1 to 6 hex digits.
+fn lex_unicode_escape(scanner: &mut Scanner, start: Location) -> LexResult<char> {
+    let mut digits = String::new();
+    let mut closed = false;
+
+    if scanner.take(&'{') {
+        while let Some(digit) = scanner.transform(|ch| ch.is_ascii_hexdigit().then_some(*ch)) {
+            digits.push(digit);
+        }
+
+        closed = scanner.take(&'}');
+    }
+
+    let code_point = if closed && (1..=6).contains(&digits.len()) {
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+    } else {
+        None
+    };
+
+    code_point.ok_or_else(|| {
+        LexError::new_span(scanner.span_from(start), LexErrorKind::InvalidUnicodeEscape)
+    })
+}
+
+fn lex_newline(scanner: &mut Scanner) -> LexResult<Token> {
+    if scanner.take(&'\n') {
+        Ok(Token::new(scanner, TokenKind::Newline))
+    } else {
+        Err(LexError::new(scanner, LexErrorKind::ExpectedNewline))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub(crate) span: Span,
+    pub(crate) kind: TokenKind,
+}
+
+impl Token {
+    pub fn new(scanner: &Scanner, kind: TokenKind) -> Self {
+        Token {
+            span: scanner.span_from(scanner.location()),
+            kind,
+        }
+    }
+
+    /// The line on which this token starts, counting from 1.
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+
+    /// The column at which this token starts, counting from 1.
+    pub fn col(&self) -> usize {
+        self.span.start.col
+    }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    /// Returns true for whitespace and comments, which have no meaning to the parser.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, TokenKind::Whitespace | TokenKind::Comment(_))
+    }
+}
+
+impl Spanned for Token {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TokenKind {
+    Id(String),
+    Mod(Mod),
+    Phrase(&'static str),
+    Whitespace,
+    Newline,
+    /// A `#` comment, holding the text after the `#`. The parser skips these, but they're kept in
+    /// the lexer's output for tools that need to preserve them.
+    Comment(String),
+    Num(i64),
+    /// A quoted string, with its escapes already resolved.
+    Str(String),
+    /// A word or double-quoted string with variable references in it.
+    Template(Template),
+    /// Input that the lexer couldn't make sense of, and has already reported as a `LexError`.
+    Error,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Id(name) => write!(f, "{}", name),
+            TokenKind::Mod(modifier) => write!(f, "{}", modifier),
+            TokenKind::Phrase(phrase) => write!(f, "{}", phrase),
+            TokenKind::Whitespace => write!(f, "whitespace"),
+            TokenKind::Newline => write!(f, "newline"),
+            TokenKind::Comment(_) => write!(f, "comment"),
+            TokenKind::Num(num) => write!(f, "{}", num),
+            TokenKind::Str(text) => write!(f, "\"{}\"", text.escape_debug()),
+            TokenKind::Template(template) => write!(f, "{}", template),
+            TokenKind::Error => write!(f, "invalid input"),
+        }
+    }
+}
+
+pub struct Scanner {
+    cursor: usize,
+    characters: Vec<char>,
+    curr_offset: usize,
+    curr_line: usize,
+    curr_col: usize,
+    file: FileId,
+}
+
+impl Scanner {
+    pub fn new(string: &str) -> Self {
+        Self::with_file(string, FileId::default())
+    }
+
+    /// Creates a scanner whose spans point into `file`.
+    pub fn with_file(string: &str, file: FileId) -> Self {
+        Self {
+            cursor: 0,
+            characters: string.chars().collect(),
+            curr_offset: 0,
+            // Files start at line 1, column 1
+            curr_line: 1,
+            curr_col: 1,
+            file,
+        }
+    }
+
+    /// Returns the current cursor. Useful for reporting errors.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the byte offset, line and column of the cursor in the original string.
+    pub fn location(&self) -> Location {
+        Location {
+            offset: self.curr_offset,
+            line: self.curr_line,
+            col: self.curr_col,
+        }
+    }
+
+    /// Returns the span from `start` up to the cursor.
+    pub fn span_from(&self, start: Location) -> Span {
+        Span {
+            start,
+            end: self.location(),
+            file: self.file,
+        }
+    }
+
+    /// Returns the next character without advancing the cursor.
+    /// AKA "lookahead"
+    pub fn peek(&self) -> Option<&char> {
+        self.characters.get(self.cursor)
+    }
+
+    /// Returns the character `n` places after the cursor without advancing it, so that
+    /// `peek_nth(0)` is the same as `peek()`.
+    pub fn peek_nth(&self, n: usize) -> Option<&char> {
+        self.characters.get(self.cursor + n)
+    }
+
+    /// Returns true if further progress is not possible
+    pub fn is_done(&self) -> bool {
+        self.cursor >= self.characters.len()
+    }
+
+    /// Returns the next character (if available) and advances the cursor.
+    pub fn pop(&mut self) -> Option<&char> {
+        match self.characters.get(self.cursor) {
+            Some(character) => {
+                if character == &'\n' {
+                    self.curr_line += 1;
+                    self.curr_col = 1;
+                } else {
+                    self.curr_col += 1;
+                }
+
+                self.cursor += 1;
+                self.curr_offset += character.len_utf8();
+
+                Some(character)
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the next character if it's in the given range, and advances the cursor.
+    /// Otherwise, returns None, leaving the cursor unchanged.
+    pub fn pop_in_range(&mut self, target_range: ops::RangeInclusive<char>) -> Option<char> {
+        match self.peek() {
+            Some(ch) => {
+                if target_range.contains(ch) {
+                    let copy = *ch;
+
+                    self.pop();
+
+                    Some(copy)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the next character if it's in the given slice, and advances the cursor.
+    /// Otherwise, returns None, leaving the cursor unchanged.
+    pub fn pop_in_slice(&mut self, range_slice: &[char]) -> Option<char> {
+        match self.peek() {
+            Some(ch) => {
+                if range_slice.contains(ch) {
+                    let copy = *ch;
+
+                    self.pop();
+
+                    Some(copy)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        }
+    }
+
+    /// Returns true if the `target` is found at the current cursor position,
+    /// and advances the cursor.
+    /// Otherwise, returns false, leaving the cursor unchanged.
+    pub fn take(&mut self, target: &char) -> bool {
+        match self.characters.get(self.cursor) {
+            Some(character) if target == character => {
+                self.pop();
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns Some(()) if the `target` is found at the current cursor position, and advances the
+    /// cursor.
+    /// Otherwise, returns None, leaving the cursor unchanged.
+    pub fn expect(&mut self, target: &char) -> LexResult<()> {
+        match self.characters.get(self.cursor) {
+            Some(character) => {
+                if target == character {
+                    self.pop();
+
+                    Ok(())
+                } else {
+                    Err(LexError::new(self, LexErrorKind::Expected(*target)))
+                }
+            }
+            None => Err(LexError::new(self, LexErrorKind::Expected(*target))),
+        }
+    }
+
+    pub fn take_str(&mut self, target: &str) -> bool {
+        if target.len() + self.cursor > self.characters.len() {
+            return false;
+        }
+
+        let mut ind = self.cursor;
+
+        for ch in target.chars() {
+            if ch != self.characters[ind] {
+                return false;
+            }
+
+            ind += 1;
+        }
+
+        let orig_cursor = self.cursor;
+
+        for _ in orig_cursor..ind {
+            self.pop();
+        }
+
+        true
+    }
+
+    /// Like `take_str`, but only matches if `target` isn't immediately followed by another word
+    /// character.
+    pub fn take_word(&mut self, target: &str) -> bool {
+        let end = self.cursor + target.chars().count();
+
+        match self.characters.get(end) {
+            Some(&next) if is_word_char(next) || next == '\\' => false,
+            _ => self.take_str(target),
+        }
+    }
+
+    /// Invoke `cb` once. If the result is not `None`, return it and advance
+    /// the cursor. Otherwise, return None and leave the cursor unchanged.
+    pub fn transform<T>(&mut self, cb: impl FnOnce(&char) -> Option<T>) -> Option<T> {
+        match self.characters.get(self.cursor) {
+            Some(input) => match cb(input) {
+                Some(output) => {
+                    self.pop();
+
+                    Some(output)
+                }
+                None => None,
+            },
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LexError {
+    position: Position,
+    kind: LexErrorKind,
+}
+
+#[derive(Debug)]
+pub enum LexErrorKind {
+    Expected(char),
+    ExpectedPhrase(&'static str),
+    ExpectedDigit,
+    ExpectedLetter,
+    ExpectedId,
+    ExpectedMod,
+    ExpectedWhitespace,
+    ExpectedNewline,
+    UnexpectedChar(char),
+    ExpectedString,
+    /// A string that is missing its closing quote before the end of the line.
+    UnterminatedString,
+    InvalidEscape(char),
+    InvalidUnicodeEscape,
+    NumberTooLarge,
+    /// A backslash in a word that isn't followed by a character to escape.
+    ExpectedEscapedChar,
+    /// A `${` that isn't followed by the name of a variable.
+    ExpectedVariableName,
+    /// A `${name` that is missing its closing `}`.
+    UnclosedVariable,
+}
+
+impl LexError {
+    /// Creates an error pointing at the character under the scanner's cursor.
+    pub(crate) fn new(scanner: &Scanner, kind: LexErrorKind) -> Self {
+        let start = scanner.location();
+        let end = match scanner.peek() {
+            Some(ch) => Location {
+                offset: start.offset + ch.len_utf8(),
+                col: start.col + 1,
+                ..start
+            },
+            None => start,
+        };
+
+        Self {
+            position: Position::Span(Span {
+                start,
+                end,
+                file: scanner.file,
+            }),
+            kind,
+        }
+    }
+
+    pub(crate) fn new_span(span: Span, kind: LexErrorKind) -> Self {
+        Self {
+            position: Position::Span(span),
+            kind,
+        }
+    }
+
+    pub fn kind(&self) -> &LexErrorKind {
+        &self.kind
+    }
+}
+
+impl Diagnostic for LexError {
+    fn position(&self) -> Position {
+        self.position
+    }
+
+    fn message(&self) -> String {
+        self.kind.to_string()
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Position::Span(span) => {
+                write!(f, "{}:{}: {}", span.start.line, span.start.col, self.kind)
+            }
+            Position::EOF => write!(f, "end of file: {}", self.kind),
+        }
+    }
+}
+
+impl Error for LexError {}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::Expected(ch) => write!(f, "expected `{}`", ch.escape_debug()),
+            LexErrorKind::ExpectedPhrase(phrase) => write!(f, "expected `{}`", phrase),
+            LexErrorKind::ExpectedDigit => write!(f, "expected a digit"),
+            LexErrorKind::ExpectedLetter => write!(f, "expected a letter"),
+            LexErrorKind::ExpectedId => write!(f, "expected an identifier"),
+            LexErrorKind::ExpectedMod => write!(f, "expected a modifier"),
+            LexErrorKind::ExpectedWhitespace => write!(f, "expected whitespace"),
+            LexErrorKind::ExpectedNewline => write!(f, "expected a newline"),
+            LexErrorKind::UnexpectedChar(ch) => {
+                write!(f, "unexpected character `{}`", ch.escape_debug())
+            }
+            LexErrorKind::ExpectedString => write!(f, "expected a quoted string"),
+            LexErrorKind::UnterminatedString => {
+                write!(f, "string is missing its closing quote")
+            }
+            LexErrorKind::InvalidEscape(ch) => {
+                write!(f, "unknown escape sequence `\\{}`", ch.escape_debug())
+            }
+            LexErrorKind::NumberTooLarge => write!(f, "number is too large"),
+            LexErrorKind::ExpectedEscapedChar => {
+                write!(f, "expected a character to escape after `\\`")
+            }
+            LexErrorKind::ExpectedVariableName => {
+                write!(f, "expected the name of a variable after `${{`")
+            }
+            LexErrorKind::UnclosedVariable => {
+                write!(f, "expected `}}` to close the variable reference")
+            }
+            LexErrorKind::InvalidUnicodeEscape => write!(
+                f,
+                "invalid unicode escape, expected `\\u{{...}}` with 1 to 6 hex digits of a valid character"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex_string_token(input: &str) -> TokenKind {
+        let tokens = lex(&mut Scanner::new(input)).unwrap();
+
+        tokens[0].kind.clone()
+    }
+
+    /// Lexes `input`, which has to fail, returning the error along with the text it points at.
+    fn lex_error(input: &str) -> (LexErrorKind, &str) {
+        let err = lex(&mut Scanner::new(input)).unwrap_err();
+
+        let text = match err.position() {
+            Position::Span(span) => span.text(input),
+            Position::EOF => "",
+        };
+
+        (err.kind, text)
+    }
+
+    #[test]
+    fn strings_with_escapes() {
+        assert_eq!(
+            lex_string_token(r#""a\tb\n\"c\" \\""#),
+            TokenKind::Str("a\tb\n\"c\" \\".to_string())
+        );
+        assert_eq!(
+            lex_string_token(r"'it\'s'"),
+            TokenKind::Str("it's".to_string())
+        );
+        assert_eq!(
+            lex_string_token(r#""\u{41}\u{1F600}\u{10FFFF}""#),
+            TokenKind::Str("A\u{1F600}\u{10FFFF}".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_unicode_escapes() {
+        for escape in [
+            r"\u{}",
+            r"\u{1234567}",
+            r"\u{110000}",
+            r"\u{D800}",
+            r"\u{41",
+            r"\u41",
+        ] {
+            let input = format!("\"{}x\"", escape);
+            let (kind, text) = lex_error(&input);
+
+            assert!(
+                matches!(kind, LexErrorKind::InvalidUnicodeEscape),
+                "{:?} gave {:?}",
+                escape,
+                kind
+            );
+            assert!(
+                text.starts_with(r"\u"),
+                "{:?} pointed at {:?}",
+                escape,
+                text
+            );
+        }
+
+        let (_, text) = lex_error(r#""a\u{110000}b""#);
+        assert_eq!(text, r"\u{110000}");
+    }
+
+    #[test]
+    fn invalid_escapes_point_at_the_escape() {
+        let (kind, text) = lex_error(r#"map x "a\qb""#);
+
+        assert!(
+            matches!(kind, LexErrorKind::InvalidEscape('q')),
+            "{:?}",
+            kind
+        );
+        assert_eq!(text, r"\q");
+    }
+
+    #[test]
+    fn unterminated_strings_point_at_the_string() {
+        let (kind, text) = lex_error("map x \"abc\nmap y z");
+
+        assert!(
+            matches!(kind, LexErrorKind::UnterminatedString),
+            "{:?}",
+            kind
+        );
+        assert_eq!(text, "\"abc");
+
+        let (kind, text) = lex_error("map x 'abc");
+
+        assert!(
+            matches!(kind, LexErrorKind::UnterminatedString),
+            "{:?}",
+            kind
+        );
+        assert_eq!(text, "'abc");
+
+        // A backslash can't escape the end of the line.
+        let (kind, _) = lex_error("map x \"abc\\\n\"");
+
+        assert!(
+            matches!(kind, LexErrorKind::UnterminatedString),
+            "{:?}",
+            kind
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Parser for `rolf`'s configuration language.
+//!
+//! Most users only need [`parse_config`], which lexes and parses a whole config file into a
+//! [`Program`], or [`load_config`], which does the same for a file on disk and the files that it
+//! sources. The [`lexer`] and [`parser`] modules are public for tools that need to work with the
+//! individual stages, and [`parse_lossless`] keeps the whitespace and comments for tools that
+//! rewrite configs.
+
+use core::fmt;
+
+pub mod ast;
+pub mod commands;
+pub mod cst;
+mod diagnostic;
+pub mod edit;
+pub mod eval;
+pub mod format;
+pub mod keymap;
+pub mod lexer;
+pub mod options;
+pub mod parser;
+pub mod source;
+pub mod span;
+mod suggest;
+pub mod validate;
+
+pub use ast::{
+    Arg, ArgKind, Cmd, CmdBody, Command, Condition, Fallback, If, Key, KeyCode, Let, Map, Mod,
+    Mode, Mods, OptionValue, Program, Set, Source, Statement, Template, TemplatePart, Unmap,
+};
+pub use commands::CommandRegistry;
+pub use cst::{parse_lossless, StatementNode, SyntaxElement, SyntaxTree};
+pub use diagnostic::{Diagnostic, Position};
+pub use edit::{Document, Target, TextEdit};
+pub use eval::Context;
+pub use format::format_config;
+pub use keymap::{Keymap, Match};
+pub use lexer::{
+    lex, lex_lossless, lex_with_errors, LexError, LexErrorKind, Scanner, Token, TokenKind,
+};
+pub use options::{Allowed, OptionSchema, OptionSpec, OptionType};
+pub use parser::{parse, parse_program, ParseError, ParseErrorKind, Parser};
+pub use source::{
+    load_config, load_config_in, Loaded, SourceError, SourceErrorKind, SourceFile, SourceMap,
+    MAX_SOURCE_DEPTH,
+};
+pub use span::{FileId, Location, Span, Spanned};
+pub use validate::{ValidationError, ValidationErrorKind};
+
+/// Lexes and parses `input` as a complete config file, failing on the first error.
+pub fn parse_config(input: &str) -> Result<Program, Error> {
+    let parsed = parse_config_with_errors(input);
+
+    match parsed.errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(parsed.program),
+    }
+}
+
+/// Lexes and parses `input` as a complete config file, carrying on past errors so that every one
+/// of them is reported. The statements that did parse are still returned, so that a host can
+/// start with the good bindings and show a list of the bad ones.
+pub fn parse_config_with_errors(input: &str) -> Parsed {
+    parse_file(input, FileId::default())
+}
+
+/// Lexes and parses `input` as the text of `file`, reporting every error.
+pub(crate) fn parse_file(input: &str, file: FileId) -> Parsed {
+    let (tokens, lex_errors) = lex_with_errors(&mut Scanner::with_file(input, file));
+
+    parse_tokens(tokens, lex_errors)
+}
+
+/// Parses the output of the lexer, reporting the lexer's errors along with the parser's.
+pub(crate) fn parse_tokens(tokens: Vec<Token>, lex_errors: Vec<LexError>) -> Parsed {
+    let (program, parse_errors) = parse_program(&mut Parser::new(tokens));
+
+    let mut errors: Vec<Error> = lex_errors.into_iter().map(Error::Lex).collect();
+    errors.extend(parse_errors.into_iter().map(Error::Parse));
+    errors.sort_by_key(Error::offset);
+
+    Parsed { program, errors }
+}
+
+/// The result of [`parse_config_with_errors`].
+#[derive(Debug)]
+pub struct Parsed {
+    /// Every statement that parsed successfully.
+    pub program: Program,
+    /// Every error in the file, in the order they appear.
+    pub errors: Vec<Error>,
+}
+
+/// An error from any stage of [`parse_config`], or from checking the parsed program against what
+/// the host supports.
+#[derive(Debug)]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+    Validation(ValidationError),
+    Source(SourceError),
+}
+
+impl From<LexError> for Error {
+    fn from(err: LexError) -> Self {
+        Error::Lex(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        Error::Validation(err)
+    }
+}
+
+impl From<SourceError> for Error {
+    fn from(err: SourceError) -> Self {
+        Error::Source(err)
+    }
+}
+
+impl Error {
+    /// The file that this error is in. Errors at the end of the file passed to [`parse_config`]
+    /// don't have a span, so they are in file 0, like the rest of that file.
+    pub fn file(&self) -> FileId {
+        match self.position() {
+            Position::Span(span) => span.file,
+            Position::EOF => FileId::default(),
+        }
+    }
+
+    /// The byte offset of this error in its file, for sorting. Errors at the end of the file sort
+    /// after everything else.
+    pub(crate) fn offset(&self) -> usize {
+        match self.position() {
+            Position::Span(span) => span.start.offset,
+            Position::EOF => usize::MAX,
+        }
+    }
+}
+
+impl Diagnostic for Error {
+    fn position(&self) -> Position {
+        match self {
+            Error::Lex(err) => err.position(),
+            Error::Parse(err) => err.position(),
+            Error::Validation(err) => err.position(),
+            Error::Source(err) => err.position(),
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Error::Lex(err) => err.message(),
+            Error::Parse(err) => err.message(),
+            Error::Validation(err) => err.message(),
+            Error::Source(err) => err.message(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(err) => write!(f, "{}", err),
+            Error::Parse(err) => write!(f, "{}", err),
+            Error::Validation(err) => write!(f, "{}", err),
+            Error::Source(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
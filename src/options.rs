@@ -0,0 +1,396 @@
+//! A schema of the options that a host supports, for checking `set` statements against.
+
+use core::fmt;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    ast::{all_statements, OptionValue, Program, Set, Statement},
+    validate::{ValidationError, ValidationErrorKind},
+};
+
+/// The type of value that an option holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionType {
+    Bool,
+    Int,
+    Str,
+    List(Box<OptionType>),
+}
+
+impl OptionType {
+    fn matches(&self, value: &OptionValue) -> bool {
+        match (self, value) {
+            (OptionType::Bool, OptionValue::Bool(_))
+            | (OptionType::Int, OptionValue::Int(_))
+            | (OptionType::Str, OptionValue::Str(_))
+            // Variables always expand to strings.
+            | (OptionType::Str, OptionValue::Template(_)) => true,
+            (OptionType::List(item_type), OptionValue::List(items)) => {
+                items.iter().all(|item| item_type.matches(item))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Describes the type with an article, as in "an integer", for use in messages.
+impl fmt::Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionType::Bool => write!(f, "a boolean"),
+            OptionType::Int => write!(f, "an integer"),
+            OptionType::Str => write!(f, "a string"),
+            OptionType::List(item_type) => match **item_type {
+                OptionType::Bool => write!(f, "a list of booleans"),
+                OptionType::Int => write!(f, "a list of integers"),
+                OptionType::Str => write!(f, "a list of strings"),
+                OptionType::List(_) => write!(f, "a list of lists"),
+            },
+        }
+    }
+}
+
+/// Which values of the right type an option accepts. For list options, this applies to each item.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Allowed {
+    #[default]
+    Any,
+    /// Integers within the range.
+    Range(RangeInclusive<i64>),
+    /// Strings that are one of these.
+    OneOf(Vec<String>),
+}
+
+/// The definition of a single option.
+#[derive(Debug, Clone)]
+pub struct OptionSpec {
+    pub name: String,
+    pub ty: OptionType,
+    pub default: OptionValue,
+    pub allowed: Allowed,
+    /// A short, human-readable explanation of what the option does.
+    pub description: String,
+}
+
+/// The options that a host supports, which it fills in before validating a program.
+#[derive(Debug, Clone, Default)]
+pub struct OptionSchema {
+    options: HashMap<String, OptionSpec>,
+}
+
+impl OptionSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an option to the schema, replacing any existing option with the same name.
+    pub fn insert(&mut self, spec: OptionSpec) {
+        self.options.insert(spec.name.clone(), spec);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OptionSpec> {
+        self.options.get(name)
+    }
+
+    /// Iterates over every option in the schema, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &OptionSpec> {
+        self.options.values()
+    }
+
+    /// Checks every `set` statement in `program` against the schema, returning an error for each
+    /// unknown option, wrongly-typed value and disallowed value, in the order they appear. `set`
+    /// statements in both branches of `if` blocks are checked too.
+    pub fn validate(&self, program: &Program) -> Vec<ValidationError> {
+        all_statements(program)
+            .into_iter()
+            .filter_map(|statement| match statement {
+                Statement::Set(set) => self.validate_set(set).err(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the value of every option after applying the valid `set` statements in `program`
+    /// to the defaults. Invalid statements are skipped, as are `if` blocks, so a program with them
+    /// should be evaluated with a [`Context`](crate::Context) first.
+    pub fn resolve(&self, program: &Program) -> BTreeMap<String, OptionValue> {
+        let mut values: BTreeMap<String, OptionValue> = self
+            .options
+            .values()
+            .map(|spec| (spec.name.clone(), spec.default.clone()))
+            .collect();
+
+        for statement in program {
+            if let Statement::Set(set) = statement {
+                if self.validate_set(set).is_ok() {
+                    let (name, value) = self.interpret(set);
+
+                    values.insert(name.to_string(), value);
+                }
+            }
+        }
+
+        values
+    }
+
+    /// Returns the name of the option that `set` sets and the value it gives it. This is what the
+    /// statement says, except that `set no<name>` turns on an option called `no<name>` if there is
+    /// one.
+    fn interpret<'a>(&self, set: &'a Set) -> (&'a str, OptionValue) {
+        match set.negated_from() {
+            Some(name) if self.options.contains_key(name) => (name, OptionValue::Bool(true)),
+            _ => (set.name(), set.value().clone()),
+        }
+    }
+
+    fn validate_set(&self, set: &Set) -> Result<(), ValidationError> {
+        let (name, value) = self.interpret(set);
+
+        let spec = self.get(name).ok_or_else(|| {
+            ValidationError::new(
+                set.name_span(),
+                ValidationErrorKind::UnknownOption(name.to_string()),
+            )
+        })?;
+
+        if !spec.ty.matches(&value) {
+            return Err(ValidationError::new(
+                set.value_span(),
+                ValidationErrorKind::WrongType {
+                    option: spec.name.clone(),
+                    expected: spec.ty.clone(),
+                    found: describe_mismatch(&spec.ty, &value),
+                },
+            ));
+        }
+
+        let items = match &value {
+            OptionValue::List(items) => items.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+
+        for item in items {
+            let kind = match (&spec.allowed, item) {
+                (Allowed::Range(range), OptionValue::Int(value)) if !range.contains(value) => {
+                    ValidationErrorKind::OutOfRange {
+                        option: spec.name.clone(),
+                        value: *value,
+                        range: range.clone(),
+                    }
+                }
+                (Allowed::OneOf(allowed), OptionValue::Str(value)) if !allowed.contains(value) => {
+                    ValidationErrorKind::NotAllowed {
+                        option: spec.name.clone(),
+                        value: value.clone(),
+                        allowed: allowed.clone(),
+                    }
+                }
+                _ => continue,
+            };
+
+            return Err(ValidationError::new(set.value_span(), kind));
+        }
+
+        Ok(())
+    }
+}
+
+/// Describes what's wrong with a value that doesn't match `ty`, for use in messages. For lists of
+/// the right kind, this points out the first item that doesn't match.
+fn describe_mismatch(ty: &OptionType, value: &OptionValue) -> String {
+    match (ty, value) {
+        (OptionType::List(item_type), OptionValue::List(items)) => {
+            match items.iter().find(|item| !item_type.matches(item)) {
+                Some(item) => format!("a list containing {}", describe_value(item)),
+                None => describe_value(value),
+            }
+        }
+        _ => describe_value(value),
+    }
+}
+
+/// Describes the type of `value` with an article, as in "a string", for use in messages.
+fn describe_value(value: &OptionValue) -> String {
+    match value {
+        OptionValue::Bool(_) => "a boolean".to_string(),
+        OptionValue::Int(_) => "an integer".to_string(),
+        OptionValue::Str(_) | OptionValue::Template(_) => "a string".to_string(),
+        OptionValue::List(_) => "a list".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config;
+
+    fn schema() -> OptionSchema {
+        let options = [
+            (
+                "hidden",
+                OptionType::Bool,
+                OptionValue::Bool(false),
+                Allowed::Any,
+            ),
+            (
+                "notify",
+                OptionType::Bool,
+                OptionValue::Bool(true),
+                Allowed::Any,
+            ),
+            (
+                "scrolloff",
+                OptionType::Int,
+                OptionValue::Int(0),
+                Allowed::Range(0..=10),
+            ),
+            (
+                "sortby",
+                OptionType::Str,
+                OptionValue::Str("natural".to_string()),
+                Allowed::OneOf(vec!["natural".to_string(), "size".to_string()]),
+            ),
+            (
+                "ratios",
+                OptionType::List(Box::new(OptionType::Int)),
+                OptionValue::List(vec![]),
+                Allowed::Range(1..=9),
+            ),
+        ];
+
+        let mut schema = OptionSchema::new();
+
+        for (name, ty, default, allowed) in options {
+            schema.insert(OptionSpec {
+                name: name.to_string(),
+                ty,
+                default,
+                allowed,
+                description: String::new(),
+            });
+        }
+
+        schema
+    }
+
+    /// Validates `input` against the schema, returning each error's message along with the text
+    /// it points at.
+    fn errors(input: &str) -> Vec<(String, &str)> {
+        schema()
+            .validate(&parse_config(input).unwrap())
+            .iter()
+            .map(|err| (err.kind().to_string(), err.span().text(input)))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_values() {
+        let input =
+            "set hidden\nset notify false\nset scrolloff 10\nset sortby size\nset ratios [1 9]";
+
+        assert!(errors(input).is_empty(), "{:?}", errors(input));
+    }
+
+    #[test]
+    fn reports_unknown_options() {
+        assert_eq!(
+            errors("set hiden"),
+            [("unknown option `hiden`".to_string(), "hiden")]
+        );
+    }
+
+    #[test]
+    fn reports_values_out_of_range() {
+        assert_eq!(
+            errors("set scrolloff 11"),
+            [(
+                "11 is out of range for option `scrolloff`, which must be from 0 to 10".to_string(),
+                "11"
+            )]
+        );
+        assert_eq!(
+            errors("set ratios [1 10]"),
+            [(
+                "10 is out of range for option `ratios`, which must be from 1 to 9".to_string(),
+                "[1 10]"
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_values_that_arent_allowed() {
+        assert_eq!(
+            errors("set sortby time"),
+            [(
+                "`time` isn't a valid value for option `sortby`, which must be one of `natural`, \
+                 `size`"
+                    .to_string(),
+                "time"
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_values_of_the_wrong_type() {
+        assert_eq!(
+            errors("set scrolloff yes"),
+            [(
+                "option `scrolloff` expects an integer, but was given a string".to_string(),
+                "yes"
+            )]
+        );
+        assert_eq!(
+            errors("set ratios [1 x 3]"),
+            [(
+                "option `ratios` expects a list of integers, but was given a list containing a \
+                 string"
+                    .to_string(),
+                "[1 x 3]"
+            )]
+        );
+        assert_eq!(
+            errors("set ratios 1"),
+            [(
+                "option `ratios` expects a list of integers, but was given an integer".to_string(),
+                "1"
+            )]
+        );
+        assert_eq!(
+            errors("set nohidden 1"),
+            [("unknown option `nohidden`".to_string(), "nohidden")]
+        );
+    }
+
+    #[test]
+    fn resolves_values_over_the_defaults() {
+        let program = parse_config("set hidden\nset scrolloff 20\nset scrolloff 5").unwrap();
+        let values = schema().resolve(&program);
+
+        assert_eq!(values["hidden"], OptionValue::Bool(true));
+        assert_eq!(values["notify"], OptionValue::Bool(true));
+        assert_eq!(values["scrolloff"], OptionValue::Int(5));
+    }
+
+    #[test]
+    fn set_no_turns_on_an_option_called_that_if_there_is_one() {
+        let program = parse_config("set notify\nset nohidden").unwrap();
+        let schema = schema();
+
+        assert!(schema.validate(&program).is_empty());
+
+        let values = schema.resolve(&program);
+        assert_eq!(values["notify"], OptionValue::Bool(true));
+        assert_eq!(values["hidden"], OptionValue::Bool(false));
+    }
+
+    #[test]
+    fn set_no_reports_the_option_it_turns_off() {
+        assert_eq!(
+            errors("set nowrap"),
+            [("unknown option `wrap`".to_string(), "nowrap")]
+        );
+    }
+}
@@ -0,0 +1,1421 @@
+use core::fmt;
+use std::{error::Error, mem};
+
+use crate::{
+    ast::{
+        fmt_keys, Arg, ArgKind, Cmd, CmdBody, Command, Condition, If, Key, KeyCode, Let, Map, Mod,
+        Mode, Mods, OptionValue, Program, Set, Source, Statement, Template, Unmap,
+    },
+    diagnostic::{Diagnostic, Position},
+    lexer::{is_word_char, Token, TokenKind},
+    span::{Location, Span, Spanned},
+    suggest::suggest,
+};
+
+pub type ParseResult<T> = std::result::Result<T, ParseError>;
+
+/// Parses a whole program, stopping at the first error.
+pub fn parse(parser: &mut Parser) -> ParseResult<Program> {
+    let (program, errors) = parse_program(parser);
+
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(program),
+    }
+}
+
+/// Parses every statement in the program. When a statement fails to parse, its error is recorded
+/// and parsing resumes on the next line, so the result holds every statement that did parse
+/// along with every error.
+pub fn parse_program(parser: &mut Parser) -> (Program, Vec<ParseError>) {
+    let mut errors = vec![];
+    let program = parse_statements(parser, &mut errors, false);
+
+    (program, errors)
+}
+
+/// Parses statements until the end of the input, recording errors in `errors` and recovering from
+/// them as `parse_program` does. Inside a block, this also stops at the `}` that closes it, which
+/// is left for the caller.
+fn parse_statements(parser: &mut Parser, errors: &mut Vec<ParseError>, in_block: bool) -> Program {
+    let mut program = vec![];
+
+    while let Some(token) = parser.peek() {
+        // Blank lines
+        if token.kind == TokenKind::Newline {
+            parser.pop();
+            continue;
+        }
+
+        if in_block && token.kind == TokenKind::Phrase("}") {
+            break;
+        }
+
+        match parse_line(parser, errors, in_block) {
+            Ok(statement) => program.push(statement),
+            Err(err) => {
+                // The lexer has already reported any input that it couldn't make sense of, so
+                // there's no need to report that the parser can't make sense of it either.
+                if !matches!(
+                    parser.peek(),
+                    Some(Token {
+                        kind: TokenKind::Error,
+                        ..
+                    })
+                ) {
+                    errors.push(err);
+                }
+
+                if in_block {
+                    parser.skip_line_in_block();
+                } else {
+                    parser.skip_line();
+                }
+            }
+        }
+    }
+
+    program
+}
+
+/// Parses a statement along with the newline that ends it. The last statement in a block can end
+/// at the block's `}` instead, as in `if os linux { set hidden }`.
+fn parse_line(
+    parser: &mut Parser,
+    errors: &mut Vec<ParseError>,
+    in_block: bool,
+) -> ParseResult<Statement> {
+    let statement = parse_statement(parser, errors)?;
+
+    match parser.peek() {
+        Some(Token {
+            kind: TokenKind::Newline,
+            ..
+        }) => {
+            parser.pop();
+        }
+        Some(Token {
+            kind: TokenKind::Phrase("}"),
+            ..
+        }) if in_block => (),
+        Some(token) => {
+            return Err(ParseError::new_pos(
+                token,
+                ParseErrorKind::Expected(TokenKind::Newline),
+            ))
+        }
+        None => (),
+    }
+
+    Ok(statement)
+}
+
+fn parse_statement(parser: &mut Parser, errors: &mut Vec<ParseError>) -> ParseResult<Statement> {
+    match parser.peek() {
+        Some(Token {
+            kind: TokenKind::Phrase("map"),
+            ..
+        }) => Ok(Statement::Map(parse_map(parser)?)),
+        Some(Token {
+            kind: TokenKind::Phrase("unmap"),
+            ..
+        }) => Ok(Statement::Unmap(parse_unmap(parser)?)),
+        Some(Token {
+            kind: TokenKind::Phrase("set"),
+            ..
+        }) => Ok(Statement::Set(parse_set(parser)?)),
+        Some(Token {
+            kind: TokenKind::Phrase("cmd"),
+            ..
+        }) => Ok(Statement::Cmd(parse_cmd(parser)?)),
+        Some(Token {
+            kind: TokenKind::Phrase("source"),
+            ..
+        }) => Ok(Statement::Source(parse_source(parser)?)),
+        Some(Token {
+            kind: TokenKind::Phrase("if"),
+            ..
+        }) => Ok(Statement::If(parse_if(parser, errors)?)),
+        Some(Token {
+            kind: TokenKind::Phrase("let"),
+            ..
+        }) => Ok(Statement::Let(parse_let(parser)?)),
+        Some(token) => Err(ParseError::new_pos(
+            token,
+            ParseErrorKind::ExpectedStatement,
+        )),
+        None => Err(ParseError::new(ParseErrorKind::ExpectedStatement)),
+    }
+}
+
+fn parse_map(parser: &mut Parser) -> ParseResult<Map> {
+    parser.expect(TokenKind::Phrase("map"))?;
+    let start = parser.prev_span();
+
+    let mode = parse_mode(parser)?;
+    let keys = parse_keys(parser, true)?;
+
+    let command = parse_command(parser).map_err(|err| {
+        err.with_kind(ParseErrorKind::ExpectedCommand {
+            key: fmt_keys(&keys),
+        })
+    })?;
+
+    Ok(Map {
+        mode,
+        keys,
+        command,
+        span: start.to(parser.prev_span()),
+    })
+}
+
+/// Parses `unmap <keys>`, or `unmap <keys> *` to remove every binding that starts with the keys.
+fn parse_unmap(parser: &mut Parser) -> ParseResult<Unmap> {
+    parser.expect(TokenKind::Phrase("unmap"))?;
+    let start = parser.prev_span();
+
+    let mode = parse_mode(parser)?;
+
+    let keys = match parser.peek() {
+        // `unmap *` removes every binding, so it doesn't need any keys.
+        Some(Token {
+            kind: TokenKind::Phrase("*"),
+            ..
+        }) => vec![],
+        _ => parse_keys(parser, false)?,
+    };
+
+    let is_prefix = parser.expect(TokenKind::Phrase("*")).is_ok();
+
+    Ok(Unmap {
+        mode,
+        keys,
+        is_prefix,
+        span: start.to(parser.prev_span()),
+    })
+}
+
+/// Parses the `[<mode>]` that can follow `map` or `unmap`, with no space after the keyword, as in
+/// `map[visual]`. Without one, the statement is for normal mode.
+fn parse_mode(parser: &mut Parser) -> ParseResult<Mode> {
+    let has_mode = matches!(
+        parser.peek(),
+        Some(Token {
+            kind: TokenKind::Phrase("["),
+            span,
+        }) if span.start == parser.prev_span().end
+    );
+
+    if !has_mode {
+        return Ok(Mode::Normal);
+    }
+
+    parser.pop();
+
+    let name = parser
+        .take_id()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedMode))?;
+    let name_span = parser.prev_span();
+
+    let mode = Mode::from_name(&name)
+        .ok_or_else(|| ParseError::new_span(name_span, ParseErrorKind::UnknownMode(name)))?;
+
+    parser.expect(TokenKind::Phrase("]"))?;
+
+    Ok(mode)
+}
+
+/// Parses `set <name> <value>`, or the boolean forms `set <name>` and `set no<name>`.
+fn parse_set(parser: &mut Parser) -> ParseResult<Set> {
+    parser.expect(TokenKind::Phrase("set"))?;
+    let start = parser.prev_span();
+
+    let name = parser
+        .take_id()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedOptionName))?;
+    let name_span = parser.prev_span();
+
+    let (name, value, value_span, negated_from) = match parse_option_value(parser)? {
+        Some((value, value_span)) => (name, value, value_span, None),
+        None => match name.strip_prefix("no") {
+            Some(rest) if !rest.is_empty() => (
+                rest.to_string(),
+                OptionValue::Bool(false),
+                name_span,
+                Some(name),
+            ),
+            _ => (name, OptionValue::Bool(true), name_span, None),
+        },
+    };
+
+    Ok(Set {
+        name,
+        name_span,
+        value,
+        value_span,
+        negated_from,
+        span: start.to(parser.prev_span()),
+    })
+}
+
+/// Parses the value in a `set` statement, if there is one.
+fn parse_option_value(parser: &mut Parser) -> ParseResult<Option<(OptionValue, Span)>> {
+    fn scalar(kind: ArgKind) -> OptionValue {
+        match kind {
+            ArgKind::Id(word) if word == "true" => OptionValue::Bool(true),
+            ArgKind::Id(word) if word == "false" => OptionValue::Bool(false),
+            ArgKind::Id(word) | ArgKind::Str(word) => OptionValue::Str(word),
+            ArgKind::Num(num) => OptionValue::Int(num),
+            ArgKind::Template(template) => OptionValue::Template(template),
+        }
+    }
+
+    if parser.expect(TokenKind::Phrase("[")).is_err() {
+        return Ok(parser.take_arg().map(|arg| (scalar(arg.kind), arg.span)));
+    }
+
+    let start = parser.prev_span();
+    let mut items = vec![];
+
+    while parser.expect(TokenKind::Phrase("]")).is_err() {
+        match parser.take_arg() {
+            Some(arg) => items.push(scalar(arg.kind)),
+            None => {
+                return Err(match parser.peek() {
+                    Some(token) => ParseError::new_pos(token, ParseErrorKind::UnclosedList),
+                    None => ParseError::new(ParseErrorKind::UnclosedList),
+                })
+            }
+        }
+    }
+
+    Ok(Some((
+        OptionValue::List(items),
+        start.to(parser.prev_span()),
+    )))
+}
+
+/// Parses `cmd <name> <body>`, where the body is either a quoted shell command or a sequence of
+/// commands separated by `;`.
+fn parse_cmd(parser: &mut Parser) -> ParseResult<Cmd> {
+    parser.expect(TokenKind::Phrase("cmd"))?;
+    let start = parser.prev_span();
+
+    let name = parser
+        .take_id()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedCmdName))?;
+    let name_span = parser.prev_span();
+
+    let shell_script = match parser.peek() {
+        Some(Token {
+            kind: TokenKind::Str(script),
+            span,
+        }) => Some((script.clone(), *span)),
+        // The shell expands variables in its script itself, so they're passed on as written.
+        Some(Token {
+            kind: TokenKind::Template(template),
+            span,
+        }) => Some((template.to_literal(), *span)),
+        _ => None,
+    };
+
+    let body = match shell_script {
+        Some((script, span)) => {
+            parser.pop();
+
+            CmdBody::Shell { script, span }
+        }
+        None => {
+            let mut commands = vec![];
+
+            loop {
+                commands.push(parse_command(parser).map_err(|err| {
+                    err.with_kind(ParseErrorKind::ExpectedCmdBody { name: name.clone() })
+                })?);
+
+                if parser.expect(TokenKind::Phrase(";")).is_err() {
+                    break;
+                }
+            }
+
+            CmdBody::Commands(commands)
+        }
+    };
+
+    Ok(Cmd {
+        name,
+        name_span,
+        body,
+        span: start.to(parser.prev_span()),
+    })
+}
+
+/// Parses a `source` statement. The path is usually quoted, but a path without spaces or special
+/// characters can be written as a bare word.
+fn parse_source(parser: &mut Parser) -> ParseResult<Source> {
+    parser.expect(TokenKind::Phrase("source"))?;
+    let start = parser.prev_span();
+
+    let path = parser
+        .take_template()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedPath))?;
+    let path_span = parser.prev_span();
+
+    Ok(Source {
+        path,
+        path_span,
+        span: start.to(path_span),
+    })
+}
+
+/// Parses `let <name> = <value>`, where the value is a single word, number or string.
+fn parse_let(parser: &mut Parser) -> ParseResult<Let> {
+    parser.expect(TokenKind::Phrase("let"))?;
+    let start = parser.prev_span();
+
+    let name = parser
+        .take_id()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedVariableName))?;
+    let name_span = parser.prev_span();
+
+    // Only names that `$name` can refer to are allowed.
+    let mut chars = name.chars();
+    let is_valid = chars
+        .next()
+        .is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_');
+
+    if !is_valid {
+        return Err(ParseError::new_span(
+            name_span,
+            ParseErrorKind::InvalidVariableName(name),
+        ));
+    }
+
+    parser.expect(TokenKind::Phrase("="))?;
+
+    let value = match parser.take_arg() {
+        Some(arg) => match arg.kind {
+            ArgKind::Id(text) | ArgKind::Str(text) => Template::from(text),
+            ArgKind::Num(num) => Template::from(num.to_string()),
+            ArgKind::Template(template) => template,
+        },
+        None => {
+            return Err(match parser.peek() {
+                Some(token) => {
+                    ParseError::new_pos(token, ParseErrorKind::ExpectedVariableValue { name })
+                }
+                None => ParseError::new(ParseErrorKind::ExpectedVariableValue { name }),
+            })
+        }
+    };
+
+    Ok(Let {
+        name,
+        name_span,
+        value,
+        span: start.to(parser.prev_span()),
+    })
+}
+
+/// Parses `if <condition> { ... }`, followed by an optional `else { ... }` or `else if ...`. The
+/// statements in each branch are parsed with the same recovery as the top level, so their errors
+/// go straight into `errors`.
+fn parse_if(parser: &mut Parser, errors: &mut Vec<ParseError>) -> ParseResult<If> {
+    parser.expect(TokenKind::Phrase("if"))?;
+    let start = parser.prev_span();
+
+    let condition_start = parser.peek().map(Spanned::span);
+
+    let condition = match parse_condition(parser) {
+        Ok(condition) => condition,
+        Err(err) => {
+            // Parse the branches anyway, so that the errors in them are still reported and their
+            // closing `}`s aren't reported as out of place.
+            while let Some(token) = parser.peek() {
+                if matches!(token.kind, TokenKind::Newline | TokenKind::Phrase("{")) {
+                    break;
+                }
+
+                parser.pop();
+            }
+
+            if parse_block(parser, errors).is_ok() {
+                parse_else(parser, errors)?;
+            }
+
+            return Err(err);
+        }
+    };
+
+    let condition_span = condition_start.unwrap_or(start).to(parser.prev_span());
+
+    let body = parse_block(parser, errors)?;
+    let else_body = parse_else(parser, errors)?;
+
+    Ok(If {
+        condition,
+        condition_span,
+        body,
+        else_body,
+        span: start.to(parser.prev_span()),
+    })
+}
+
+/// Parses the `else { ... }` or `else if ...` after the body of an `if` block, if there is one.
+fn parse_else(parser: &mut Parser, errors: &mut Vec<ParseError>) -> ParseResult<Option<Program>> {
+    if parser.expect(TokenKind::Phrase("else")).is_err() {
+        return Ok(None);
+    }
+
+    match parser.peek() {
+        Some(Token {
+            kind: TokenKind::Phrase("if"),
+            ..
+        }) => Ok(Some(vec![Statement::If(parse_if(parser, errors)?)])),
+        _ => Ok(Some(parse_block(parser, errors)?)),
+    }
+}
+
+/// Parses a condition such as `os linux` or `not env SSH_CONNECTION`.
+fn parse_condition(parser: &mut Parser) -> ParseResult<Condition> {
+    let name = parser
+        .take_id()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedCondition))?;
+    let name_span = parser.prev_span();
+
+    if name == "not" {
+        return Ok(Condition::Not(Box::new(parse_condition(parser)?)));
+    }
+
+    // Paths are the only values that can refer to variables.
+    if name == "exists" {
+        let path = parser.take_template().map_err(|err| {
+            err.with_kind(ParseErrorKind::ExpectedConditionValue { condition: name })
+        })?;
+
+        return Ok(Condition::Exists(path));
+    }
+
+    let condition: fn(String) -> Condition = match name.as_str() {
+        "os" => Condition::Os,
+        "host" => Condition::Host,
+        "term" => Condition::Term,
+        "env" => Condition::Env,
+        _ => {
+            return Err(ParseError::new_span(
+                name_span,
+                ParseErrorKind::UnknownCondition(name),
+            ))
+        }
+    };
+
+    let value = parser
+        .take_text()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedConditionValue { condition: name }))?;
+
+    Ok(condition(value))
+}
+
+/// Parses a `{`, the lines of statements after it and the `}` that closes them.
+fn parse_block(parser: &mut Parser, errors: &mut Vec<ParseError>) -> ParseResult<Program> {
+    parser.expect(TokenKind::Phrase("{"))?;
+
+    let program = parse_statements(parser, errors, true);
+
+    parser
+        .expect(TokenKind::Phrase("}"))
+        .map_err(|err| err.with_kind(ParseErrorKind::UnclosedBlock))?;
+
+    Ok(program)
+}
+
+/// Parses a command name followed by its arguments, which run until the end of the line.
+fn parse_command(parser: &mut Parser) -> ParseResult<Command> {
+    let name = parser.take_id()?;
+    let name_span = parser.prev_span();
+
+    let mut args = vec![];
+
+    while let Some(arg) = parser.take_arg() {
+        args.push(arg);
+    }
+
+    Ok(Command {
+        name,
+        name_span,
+        args,
+        span: name_span.to(parser.prev_span()),
+    })
+}
+
+/// Parses a sequence of key chords, which may be written either as separate words (`g g`) or in a
+/// compact form (`gg`).
+///
+/// The first word is always part of the sequence. When a command follows the sequence, as in a
+/// `map` statement, a later word is only taken as a chord if it has modifiers, or if it's a single
+/// character with another word after it to be the command. So `map g g top` binds `g g`, while
+/// `map j down` binds `j` to the `down` command. Otherwise, every word up to the end of the
+/// statement is a chord.
+fn parse_keys(parser: &mut Parser, command_follows: bool) -> ParseResult<Vec<Key>> {
+    let mut keys = match parser.peek() {
+        Some(Token {
+            kind: TokenKind::Mod(_),
+            ..
+        }) => vec![parse_key(parser)?],
+        _ => parse_compact_keys(parser)?,
+    };
+
+    let word_follows = |parser: &Parser| {
+        matches!(
+            parser.peek_nth(1),
+            Some(Token {
+                kind: TokenKind::Id(_),
+                ..
+            })
+        )
+    };
+
+    loop {
+        let is_chord = match parser.peek().map(|token| &token.kind) {
+            Some(TokenKind::Mod(_)) => true,
+            Some(TokenKind::Id(_) | TokenKind::Num(_)) if !command_follows => true,
+            Some(TokenKind::Id(name)) => name.chars().count() == 1 && word_follows(parser),
+            Some(TokenKind::Num(num)) => (0..=9).contains(num) && word_follows(parser),
+            // After the keys of an `unmap`, a `*` means every binding that starts with them.
+            Some(TokenKind::Phrase("*")) => command_follows && word_follows(parser),
+            Some(TokenKind::Phrase("=")) => !command_follows || word_follows(parser),
+            _ => false,
+        };
+
+        if !is_chord {
+            break;
+        }
+
+        keys.push(parse_key(parser)?);
+    }
+
+    Ok(keys)
+}
+
+/// Parses a word without modifiers at the start of a key sequence. A word that names a key is
+/// that key, and any other word is a compact sequence with one chord per character, as in `gg`.
+///
+/// A word that looks like a mistyped key is reported rather than bound as a sequence, so that
+/// `entr` or `ctrl-k` doesn't quietly bind `e n t r` or `c t r l - k`. That's a word close to the
+/// name of a key, `f` followed by digits, or a modifier followed by `-`. A sequence like that can
+/// still be bound by writing its keys apart, as in `e n t r`.
+fn parse_compact_keys(parser: &mut Parser) -> ParseResult<Vec<Key>> {
+    let name = parser
+        .take_key_name()
+        .map_err(|err| err.with_kind(ParseErrorKind::ExpectedKey { modifier: None }))?;
+
+    let span = parser.prev_span();
+
+    if let Some(code) = KeyCode::from_name(&name) {
+        return Ok(vec![Key {
+            modifiers: Mods::NONE,
+            code,
+            span,
+        }]);
+    }
+
+    if let Some(kind) = misspelled_key(&name) {
+        return Err(ParseError::new_span(span, kind));
+    }
+
+    // Characters that can't appear in a bare word must have been escaped with a backslash, so
+    // that's how much source each character took up. If the word has escapes that weren't needed,
+    // the chords can't be told apart in the source, and each gets the span of the whole word.
+    let escape_len = |ch: char| usize::from(!is_word_char(ch));
+    let source_len: usize = name.chars().map(|ch| escape_len(ch) + ch.len_utf8()).sum();
+    let is_exact = source_len == span.len();
+
+    let mut start = span.start;
+
+    let keys = name
+        .chars()
+        .map(|ch| {
+            let chord_span = if is_exact {
+                let end = Location {
+                    offset: start.offset + escape_len(ch) + ch.len_utf8(),
+                    col: start.col + escape_len(ch) + 1,
+                    ..start
+                };
+
+                Span {
+                    start: mem::replace(&mut start, end),
+                    end,
+                    ..span
+                }
+            } else {
+                span
+            };
+
+            Key {
+                modifiers: Mods::NONE,
+                code: KeyCode::Char(ch),
+                span: chord_span,
+            }
+        })
+        .collect();
+
+    Ok(keys)
+}
+
+fn parse_key(parser: &mut Parser) -> ParseResult<Key> {
+    let start = parser.peek().map(|token| token.span());
+
+    let mut modifiers = Mods::NONE;
+    let mut last_modifier = None;
+
+    while let Ok(modifier) = parser.take_mod() {
+        if !modifiers.insert(modifier) {
+            return Err(ParseError::new_span(
+                parser.prev_span(),
+                ParseErrorKind::RepeatedModifier(modifier),
+            ));
+        }
+
+        parser.expect(TokenKind::Phrase("+"))?;
+
+        last_modifier = Some(modifier);
+    }
+
+    // The parser doesn't see whitespace, so check that the key touches the `+`. Otherwise
+    // `ctrl+ x` would quietly bind `ctrl+x`.
+    if last_modifier.is_some()
+        && !matches!(parser.peek(), Some(token) if token.span().start == parser.prev_span().end)
+    {
+        return Err(ParseError::new_span(
+            parser.prev_span(),
+            ParseErrorKind::ExpectedKey {
+                modifier: last_modifier,
+            },
+        ));
+    }
+
+    let name = parser.take_key_name().map_err(|err| {
+        err.with_kind(ParseErrorKind::ExpectedKey {
+            modifier: last_modifier,
+        })
+    })?;
+
+    let end = parser.prev_span();
+
+    let code = KeyCode::from_name(&name).ok_or_else(|| unknown_key(end, name))?;
+
+    Ok(Key {
+        code,
+        modifiers,
+        span: start.map_or(end, |start| start.to(end)),
+    })
+}
+
+/// Returns the error for a key name at `span` that isn't a key, suggesting a key with a similar
+/// name if there is one.
+fn unknown_key(span: Span, name: String) -> ParseError {
+    let suggestion = suggest(&name.to_lowercase(), KeyCode::NAMES.iter().copied());
+
+    ParseError::new_span(
+        span,
+        ParseErrorKind::UnknownKey {
+            name,
+            suggestion: suggestion.map(str::to_string),
+        },
+    )
+}
+
+/// Returns the error for `word`, the start of a key sequence, if it looks like a mistyped key
+/// rather than a compact sequence: a modifier followed by `-` instead of `+`, `f` followed by
+/// digits, or a word close to the name of a key.
+fn misspelled_key(word: &str) -> Option<ParseErrorKind> {
+    let mut modifiers = String::new();
+    let mut rest = word;
+
+    while let Some((modifier, after)) = Mod::ALL.iter().find_map(|modifier| {
+        let name = modifier.to_string();
+        let (prefix, after) = rest.split_at_checked(name.len())?;
+
+        prefix
+            .eq_ignore_ascii_case(&name)
+            .then_some((modifier, after.strip_prefix('-')?))
+    }) {
+        modifiers.push_str(&format!("{}+", modifier));
+        rest = after;
+    }
+
+    let suggestion = if !modifiers.is_empty() {
+        Some(format!("{}{}", modifiers, rest))
+    } else if rest
+        .strip_prefix(['f', 'F'])
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_digit()))
+    {
+        None
+    } else if rest.chars().count() >= 3 {
+        // Shorter words are much more likely to be a sequence than a misspelled name.
+        Some(suggest(&rest.to_lowercase(), KeyCode::NAMES.iter().copied())?.to_string())
+    } else {
+        return None;
+    };
+
+    Some(ParseErrorKind::UnknownKey {
+        name: word.to_string(),
+        suggestion,
+    })
+}
+
+#[derive(Debug)]
+pub struct Parser {
+    cursor: usize,
+    tokens: Vec<Token>,
+}
+
+impl Parser {
+    /// Creates a parser over the output of the lexer. Comments and whitespace have no meaning to
+    /// the parser, so they're dropped here.
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        tokens.retain(|token| !token.is_trivia());
+
+        Self { cursor: 0, tokens }
+    }
+
+    /// Returns the current cursor. Useful for reporting errors.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the next character without advancing the cursor.
+    /// AKA "lookahead"
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor)
+    }
+
+    /// Returns the token `n` tokens after the next one without advancing the cursor.
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.cursor + n)
+    }
+
+    /// Returns true if further progress is not possible
+    pub fn is_done(&self) -> bool {
+        self.cursor >= self.tokens.len()
+    }
+
+    /// Returns the span of the most recently consumed token. Useful for finding where a node
+    /// ends.
+    ///
+    /// Panics if no tokens have been consumed yet.
+    pub fn prev_span(&self) -> Span {
+        self.tokens[self.cursor - 1].span()
+    }
+
+    /// Advances the cursor past the next newline, or to the end of the tokens if there isn't one.
+    pub fn skip_line(&mut self) {
+        while let Some(token) = self.pop() {
+            if token.kind == TokenKind::Newline {
+                break;
+            }
+        }
+    }
+
+    /// Like `skip_line`, but also stops before a `}` that closes the block the cursor is in, so
+    /// that a bad statement on the same line as the end of its block doesn't take the `}` with
+    /// it. Blocks that open while skipping are skipped whole.
+    pub fn skip_line_in_block(&mut self) {
+        let mut depth = 0;
+
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Phrase("}") if depth == 0 => break,
+                TokenKind::Phrase("}") => depth -= 1,
+                TokenKind::Phrase("{") => depth += 1,
+                TokenKind::Newline if depth == 0 => {
+                    self.pop();
+                    break;
+                }
+                _ => (),
+            }
+
+            self.pop();
+        }
+    }
+
+    /// Returns the next character (if available) and advances the cursor.
+    pub fn pop(&mut self) -> Option<&Token> {
+        match self.tokens.get(self.cursor) {
+            Some(token) => {
+                self.cursor += 1;
+
+                Some(token)
+            }
+            None => None,
+        }
+    }
+
+    pub fn take_id(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Id(name),
+                ..
+            }) => {
+                let copy = name.clone();
+
+                self.pop();
+
+                Ok(copy)
+            }
+            Some(token) => Err(ParseError::new_pos(token, ParseErrorKind::ExpectedId)),
+            None => Err(ParseError::new(ParseErrorKind::ExpectedId)),
+        }
+    }
+
+    /// Returns the text of the next token if it's a word or a string, and advances the cursor.
+    /// Useful for paths and patterns, which only need quoting if they contain special characters.
+    pub fn take_text(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Id(text) | TokenKind::Str(text),
+                ..
+            }) => {
+                let copy = text.clone();
+
+                self.pop();
+
+                Ok(copy)
+            }
+            Some(token) => Err(ParseError::new_pos(token, ParseErrorKind::ExpectedId)),
+            None => Err(ParseError::new(ParseErrorKind::ExpectedId)),
+        }
+    }
+
+    /// Like `take_text`, but also accepts a word or string with variable references in it.
+    pub fn take_template(&mut self) -> ParseResult<Template> {
+        if let Some(Token {
+            kind: TokenKind::Template(template),
+            ..
+        }) = self.peek()
+        {
+            let copy = template.clone();
+
+            self.pop();
+
+            return Ok(copy);
+        }
+
+        self.take_text().map(Template::from)
+    }
+
+    /// Returns the text of the next token if it can name a key, and advances the cursor. Digit
+    /// keys are lexed as numbers, and a `*` or `=` on its own is lexed as a keyword for `unmap` or
+    /// `let`, so those are accepted as well as identifiers.
+    pub fn take_key_name(&mut self) -> ParseResult<String> {
+        let name = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Num(num),
+                ..
+            }) => num.to_string(),
+            Some(Token {
+                kind: TokenKind::Phrase(phrase @ ("*" | "=")),
+                ..
+            }) => phrase.to_string(),
+            _ => return self.take_id(),
+        };
+
+        self.pop();
+
+        Ok(name)
+    }
+
+    /// Returns the next token as a command argument if it can be one, and advances the cursor.
+    /// Otherwise, returns None, leaving the cursor unchanged.
+    ///
+    /// Keywords are only special at the start of a statement, so they're taken as plain
+    /// identifiers here.
+    pub fn take_arg(&mut self) -> Option<Arg> {
+        let token = self.peek()?;
+
+        let kind = match &token.kind {
+            TokenKind::Id(name) => ArgKind::Id(name.clone()),
+            TokenKind::Str(text) => ArgKind::Str(text.clone()),
+            TokenKind::Num(num) => ArgKind::Num(*num),
+            TokenKind::Template(template) => ArgKind::Template(template.clone()),
+            TokenKind::Mod(modifier) => ArgKind::Id(modifier.to_string()),
+            TokenKind::Phrase(phrase) if phrase.chars().all(is_word_char) => {
+                ArgKind::Id(phrase.to_string())
+            }
+            _ => return None,
+        };
+
+        let span = token.span();
+
+        self.pop();
+
+        Some(Arg { kind, span })
+    }
+
+    pub fn take_mod(&mut self) -> ParseResult<Mod> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Mod(mod_enum),
+                ..
+            }) => {
+                let copy = *mod_enum;
+
+                self.pop();
+
+                Ok(copy)
+            }
+            Some(token) => Err(ParseError::new_pos(token, ParseErrorKind::ExpectedMod)),
+            None => Err(ParseError::new(ParseErrorKind::ExpectedMod)),
+        }
+    }
+
+    /// Returns Some(()) if the `target` is found at the current cursor position, and advances the
+    /// cursor.
+    /// Otherwise, returns None, leaving the cursor unchanged.
+    pub fn expect(&mut self, target: TokenKind) -> ParseResult<()> {
+        match self.tokens.get(self.cursor) {
+            Some(token) => {
+                if target == token.kind {
+                    self.pop();
+
+                    Ok(())
+                } else {
+                    Err(ParseError::new_pos(token, ParseErrorKind::Expected(target)))
+                }
+            }
+            None => Err(ParseError::new(ParseErrorKind::Expected(target))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    position: Position,
+    kind: ParseErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Message(String),
+    RemainingTokens,
+    Expected(TokenKind),
+    /// A line that doesn't start with a statement keyword.
+    ExpectedStatement,
+    ExpectedId,
+    ExpectedMod,
+    ExpectedEof,
+    /// A `map` statement is missing the key to bind, or a modifier isn't followed by one.
+    ExpectedKey {
+        modifier: Option<Mod>,
+    },
+    /// A key name that is neither a single character nor one of the names in `KeyCode`, along
+    /// with the name of a key that it could be a typo of.
+    UnknownKey {
+        name: String,
+        suggestion: Option<String>,
+    },
+    /// The same modifier appears more than once in a key, as in `ctrl+ctrl+k`.
+    RepeatedModifier(Mod),
+    /// A `set` statement is missing the name of the option to set.
+    ExpectedOptionName,
+    /// A list value is missing its closing `]`, or holds something other than plain values.
+    UnclosedList,
+    /// A `cmd` statement is missing the name of the command to define.
+    ExpectedCmdName,
+    /// A `cmd` statement is missing its body, or has a `;` that isn't followed by a command.
+    ExpectedCmdBody {
+        name: String,
+    },
+    /// A `map` statement is missing the command that its key is bound to.
+    ExpectedCommand {
+        key: String,
+    },
+    /// A `source` statement is missing the path of the file to load.
+    ExpectedPath,
+    /// A `[` after `map` or `unmap` isn't followed by the name of a mode.
+    ExpectedMode,
+    UnknownMode(String),
+    /// An `if` is missing its condition.
+    ExpectedCondition,
+    UnknownCondition(String),
+    /// A condition is missing the pattern, variable or path that it tests.
+    ExpectedConditionValue {
+        condition: String,
+    },
+    /// A block is missing the `}` that closes it.
+    UnclosedBlock,
+    /// A `let` statement is missing the name of the variable to define.
+    ExpectedVariableName,
+    /// A `let` statement names a variable that couldn't be referred to, such as `my-var`.
+    InvalidVariableName(String),
+    /// A `let` statement is missing the value to give its variable.
+    ExpectedVariableValue {
+        name: String,
+    },
+}
+
+impl ParseError {
+    pub(crate) fn new(kind: ParseErrorKind) -> Self {
+        Self {
+            position: Position::EOF,
+            kind,
+        }
+    }
+
+    pub(crate) fn new_pos(token: &Token, kind: ParseErrorKind) -> Self {
+        Self {
+            position: Position::Span(token.span()),
+            kind,
+        }
+    }
+
+    pub(crate) fn new_span(span: Span, kind: ParseErrorKind) -> Self {
+        Self {
+            position: Position::Span(span),
+            kind,
+        }
+    }
+
+    /// Replaces the kind of this error, keeping its position. Useful for giving a generic error
+    /// more context.
+    pub(crate) fn with_kind(self, kind: ParseErrorKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    /// Points an error at the end of the file at `eof` instead, so that it says which file it
+    /// came from. Errors that already have a span are left alone.
+    pub(crate) fn with_eof_span(self, eof: Span) -> Self {
+        match self.position {
+            Position::EOF => Self {
+                position: Position::Span(eof),
+                ..self
+            },
+            Position::Span(_) => self,
+        }
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn position(&self) -> Position {
+        self.position
+    }
+
+    fn message(&self) -> String {
+        self.kind.to_string()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Position::Span(span) => {
+                write!(f, "{}:{}: {}", span.start.line, span.start.col, self.kind)
+            }
+            Position::EOF => write!(f, "end of file: {}", self.kind),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Message(message) => write!(f, "{}", message),
+            ParseErrorKind::RemainingTokens => {
+                write!(f, "unexpected input after the last statement")
+            }
+            ParseErrorKind::Expected(TokenKind::Newline) => {
+                write!(f, "expected the end of the line")
+            }
+            ParseErrorKind::Expected(kind) => write!(f, "expected `{}`", kind),
+            ParseErrorKind::ExpectedStatement => {
+                write!(f, "expected a statement, such as `map`, `set` or `cmd`")
+            }
+            ParseErrorKind::ExpectedId => write!(f, "expected an identifier"),
+            ParseErrorKind::ExpectedMod => write!(f, "expected a modifier such as `ctrl`"),
+            ParseErrorKind::ExpectedEof => write!(f, "expected the end of the file"),
+            ParseErrorKind::ExpectedKey {
+                modifier: Some(modifier),
+            } => {
+                write!(f, "expected a key after `{}+`", modifier)
+            }
+            ParseErrorKind::ExpectedKey { modifier: None } => write!(f, "expected a key"),
+            ParseErrorKind::ExpectedOptionName => write!(f, "expected the name of an option"),
+            ParseErrorKind::UnclosedList => {
+                write!(f, "expected `]` to close the list, or a value to put in it")
+            }
+            ParseErrorKind::ExpectedCmdName => {
+                write!(f, "expected the name of the command to define")
+            }
+            ParseErrorKind::ExpectedCmdBody { name } => write!(
+                f,
+                "expected a command or a quoted shell command for `{}` to run",
+                name
+            ),
+            ParseErrorKind::UnknownKey {
+                name,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown key `{}`, did you mean `{}`?", name, suggestion),
+            ParseErrorKind::UnknownKey {
+                name,
+                suggestion: None,
+            } => write!(f, "unknown key `{}`", name),
+            ParseErrorKind::RepeatedModifier(modifier) => {
+                write!(f, "modifier `{}` is repeated", modifier)
+            }
+            ParseErrorKind::ExpectedCommand { key } => {
+                write!(f, "expected a command name after key `{}`", key)
+            }
+            ParseErrorKind::ExpectedPath => write!(f, "expected the path of a file to source"),
+            ParseErrorKind::ExpectedMode => write!(f, "expected the name of a mode"),
+            ParseErrorKind::ExpectedCondition => write!(f, "expected a condition after `if`"),
+            ParseErrorKind::UnknownCondition(name) => write!(
+                f,
+                "unknown condition `{}`, which must be one of os, host, term, env, exists or not",
+                name
+            ),
+            ParseErrorKind::ExpectedConditionValue { condition } => {
+                write!(f, "expected something for `{}` to test", condition)
+            }
+            ParseErrorKind::UnclosedBlock => write!(f, "expected `}}` to close the block"),
+            ParseErrorKind::ExpectedVariableName => {
+                write!(f, "expected the name of the variable to define")
+            }
+            ParseErrorKind::InvalidVariableName(name) => write!(
+                f,
+                "`{}` can't be used as a variable name, which can only contain letters, digits and underscores, and can't start with a digit",
+                name
+            ),
+            ParseErrorKind::ExpectedVariableValue { name } => {
+                write!(f, "expected a value for `{}`", name)
+            }
+            ParseErrorKind::UnknownMode(name) => write!(
+                f,
+                "unknown mode `{}`, which must be one of normal, visual, command or preview",
+                name
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_config, parse_config_with_errors, Error};
+
+    /// Parses `input`, which has to be a single statement, and returns it.
+    fn statement(input: &str) -> Statement {
+        let mut program = parse_config(input).unwrap();
+        assert_eq!(program.len(), 1, "parsing {:?}", input);
+
+        program.remove(0)
+    }
+
+    /// Returns the keys bound by the `map` statement in `input`, written canonically.
+    fn map_keys(input: &str) -> String {
+        match statement(input) {
+            Statement::Map(map) => fmt_keys(map.keys()),
+            statement => panic!("expected a map statement, got {:?}", statement),
+        }
+    }
+
+    /// Returns the message of the first error in `input`, along with the text it points at.
+    fn error(input: &str) -> (String, &str) {
+        let err = match parse_config(input).unwrap_err() {
+            Error::Parse(err) => err,
+            err => panic!("expected a parse error, got {:?}", err),
+        };
+
+        let text = match err.position() {
+            Position::Span(span) => span.text(input),
+            Position::EOF => "",
+        };
+
+        (err.kind().to_string(), text)
+    }
+
+    #[test]
+    fn modifiers_can_be_written_in_any_order() {
+        assert_eq!(map_keys("map shift+ctrl+k up"), "ctrl+shift+k");
+        assert_eq!(
+            map_keys("map alt+ctrl+shift+Home top"),
+            "ctrl+shift+alt+home"
+        );
+        assert_eq!(
+            error("map ctrl+shift+ctrl+k up"),
+            ("modifier `ctrl` is repeated".to_string(), "ctrl")
+        );
+    }
+
+    #[test]
+    fn keys_have_to_touch_their_modifiers() {
+        let expected = ("expected a key after `ctrl+`".to_string(), "+");
+
+        assert_eq!(error("map ctrl+ x up"), expected);
+        assert_eq!(error("map ctrl+ x"), expected);
+        assert_eq!(error("map ctrl+"), expected);
+    }
+
+    #[test]
+    fn compact_sequences() {
+        assert_eq!(map_keys("map gg top"), "g g");
+        assert_eq!(map_keys("map gcc comment"), "g c c");
+        assert_eq!(map_keys("map zzz x"), "z z z");
+        assert_eq!(map_keys("map g? help"), "g ?");
+        assert_eq!(map_keys("map f5 reload"), "f5");
+        assert_eq!(map_keys("map Enter open"), "enter");
+    }
+
+    #[test]
+    fn compact_key_typos_are_reported() {
+        assert_eq!(
+            error("map entr open"),
+            (
+                "unknown key `entr`, did you mean `enter`?".to_string(),
+                "entr"
+            )
+        );
+        assert_eq!(
+            error("map pgdown down"),
+            (
+                "unknown key `pgdown`, did you mean `pagedown`?".to_string(),
+                "pgdown"
+            )
+        );
+        assert_eq!(
+            error("map ctrl-k up"),
+            (
+                "unknown key `ctrl-k`, did you mean `ctrl+k`?".to_string(),
+                "ctrl-k"
+            )
+        );
+        assert_eq!(
+            error("map Alt-shift-x up"),
+            (
+                "unknown key `Alt-shift-x`, did you mean `alt+shift+x`?".to_string(),
+                "Alt-shift-x"
+            )
+        );
+        assert_eq!(error("map f25 x"), ("unknown key `f25`".to_string(), "f25"));
+    }
+
+    #[test]
+    fn star_is_a_key_and_ends_an_unmap() {
+        assert_eq!(map_keys("map * x"), "*");
+        assert_eq!(map_keys("map ctrl+* x"), "ctrl+*");
+        assert_eq!(map_keys("map g * x"), "g *");
+
+        for (input, keys, is_prefix) in [
+            ("unmap g", "g", false),
+            ("unmap g *", "g", true),
+            ("unmap g g *", "g g", true),
+            ("unmap *", "", true),
+            ("unmap \\*", "*", false),
+            ("unmap[visual] \\* *", "*", true),
+        ] {
+            match statement(input) {
+                Statement::Unmap(unmap) => {
+                    assert_eq!(fmt_keys(unmap.keys()), keys, "parsing {:?}", input);
+                    assert_eq!(unmap.is_prefix(), is_prefix, "parsing {:?}", input);
+                }
+                statement => panic!("expected an unmap statement, got {:?}", statement),
+            }
+        }
+    }
+
+    #[test]
+    fn set_statements() {
+        for (input, name, value, negated_from) in [
+            ("set hidden", "hidden", OptionValue::Bool(true), None),
+            ("set hidden false", "hidden", OptionValue::Bool(false), None),
+            (
+                "set nohidden",
+                "hidden",
+                OptionValue::Bool(false),
+                Some("nohidden"),
+            ),
+            ("set notify true", "notify", OptionValue::Bool(true), None),
+            ("set no", "no", OptionValue::Bool(true), None),
+            (
+                "set shell 'zsh -l'",
+                "shell",
+                OptionValue::Str("zsh -l".to_string()),
+                None,
+            ),
+            (
+                "set ratios [1 2 x]",
+                "ratios",
+                OptionValue::List(vec![
+                    OptionValue::Int(1),
+                    OptionValue::Int(2),
+                    OptionValue::Str("x".to_string()),
+                ]),
+                None,
+            ),
+        ] {
+            match statement(input) {
+                Statement::Set(set) => {
+                    assert_eq!(set.name(), name, "parsing {:?}", input);
+                    assert_eq!(set.value(), &value, "parsing {:?}", input);
+                    assert_eq!(set.negated_from(), negated_from, "parsing {:?}", input);
+                }
+                statement => panic!("expected a set statement, got {:?}", statement),
+            }
+        }
+    }
+
+    #[test]
+    fn recovers_from_errors_inside_blocks() {
+        let parsed = parse_config_with_errors("if os linux { map q }\nmap j down\nmap k up\n");
+
+        assert_eq!(parsed.program.len(), 3);
+        assert_eq!(parsed.errors.len(), 1);
+
+        let parsed = parse_config_with_errors(
+            "if os linux {\n    map q\n    map j down\n} else { bogus }\nmap k up\n",
+        );
+
+        assert_eq!(parsed.program.len(), 2);
+        assert_eq!(parsed.errors.len(), 2);
+        match &parsed.program[0] {
+            Statement::If(if_block) => {
+                assert_eq!(if_block.body().len(), 1);
+                assert_eq!(if_block.else_body().map(<[_]>::len), Some(0));
+            }
+            statement => panic!("expected an if block, got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn recovery_in_a_block_skips_nested_blocks() {
+        let parsed = parse_config_with_errors(
+            "if os linux {\n    map q { if env X { map k up } }\n    map j down\n}\nmap k up\n",
+        );
+
+        assert_eq!(parsed.program.len(), 2);
+        assert_eq!(parsed.errors.len(), 1);
+    }
+
+    #[test]
+    fn equals_is_a_key_outside_let() {
+        assert_eq!(map_keys("map = x"), "=");
+        assert_eq!(map_keys("map ctrl+= x"), "ctrl+=");
+        assert_eq!(map_keys("map g = x"), "g =");
+
+        match statement("unmap =") {
+            Statement::Unmap(unmap) => assert_eq!(fmt_keys(unmap.keys()), "="),
+            statement => panic!("expected an unmap statement, got {:?}", statement),
+        }
+
+        match statement("let a = 1") {
+            Statement::Let(let_statement) => assert_eq!(let_statement.name(), "a"),
+            statement => panic!("expected a let statement, got {:?}", statement),
+        }
+    }
+}
@@ -0,0 +1,562 @@
+//! Loading config files from disk, following the `source` statements that pull other files in.
+
+use core::fmt;
+use std::{
+    error::Error as StdError,
+    fs, io,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    vec,
+};
+
+use crate::{
+    ast::{Program, Source, Statement},
+    diagnostic::{self, Diagnostic, Position},
+    eval::{expand, Context},
+    parse_file,
+    span::{FileId, Span, Spanned},
+    Error,
+};
+
+/// How deeply `source` statements can nest before loading gives up, counting the file passed to
+/// [`load_config`] as the first level. This stops a runaway chain of files that never quite forms
+/// a cycle, such as one that sources a freshly generated file each time.
+pub const MAX_SOURCE_DEPTH: usize = 16;
+
+/// Loads the config file at `path` along with every file that it sources, carrying on past errors
+/// as [`parse_config_with_errors`](crate::parse_config_with_errors) does.
+///
+/// Each `source` statement is replaced by the statements of the file that it names, so the
+/// returned program reads as if the files had been pasted together. Every span in the program and
+/// in the errors says which file it came from, which [`SourceMap::render`] uses to quote the right
+/// one.
+///
+/// Only failing to read `path` itself is an I/O error. A sourced file that can't be read is
+/// reported as an [`Error::Source`] at the `source` statement that names it.
+///
+/// The paths in `source` statements can refer to environment variables and start with `~`, which
+/// are expanded from the process's environment. They're expanded while loading, before any `let`
+/// statements apply, so they can't refer to variables that those define.
+///
+/// A `source` statement in an `if` block is only followed if its branch applies on this machine,
+/// so a file can source another that only some machines have. The conditions are tested while
+/// loading too, so an `exists` path can't refer to `let` variables either.
+pub fn load_config(path: impl AsRef<Path>) -> io::Result<Loaded> {
+    load_config_in(path, &Context::from_system())
+}
+
+/// Like [`load_config`], but tests the conditions of `if` blocks against `context`, and expands
+/// the paths in `source` statements from its environment, rather than the process's.
+pub fn load_config_in(path: impl AsRef<Path>, context: &Context) -> io::Result<Loaded> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let canonical = fs::canonicalize(path)?;
+
+    let mut loader = Loader {
+        context,
+        sources: SourceMap::new(),
+        errors: vec![],
+        stack: vec![],
+    };
+    let program = loader.load(path.to_path_buf(), canonical, text);
+
+    Ok(Loaded {
+        program,
+        errors: loader.errors,
+        sources: loader.sources,
+    })
+}
+
+/// The result of [`load_config`].
+#[derive(Debug)]
+pub struct Loaded {
+    /// Every statement that parsed successfully, from every file, in the order that they apply.
+    pub program: Program,
+    /// Every error in every file, in the order they were reached.
+    pub errors: Vec<Error>,
+    /// The text of every file that was loaded, for rendering errors.
+    pub sources: SourceMap,
+}
+
+/// The files that were loaded, indexed by the [`FileId`] in each span.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file and returns the id to lex it with.
+    pub fn add(&mut self, path: PathBuf, text: String) -> FileId {
+        self.files.push(SourceFile { path, text });
+
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| (FileId(index), file))
+    }
+
+    /// Renders `err` as a report that quotes the file it came from.
+    pub fn render(&self, err: &Error) -> String {
+        match self.get(err.file()) {
+            Some(file) => err.render(&file.path.display().to_string(), &file.text),
+            None => format!("error: {}", err),
+        }
+    }
+}
+
+/// A file that was loaded, as it was when it was read.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    path: PathBuf,
+    text: String,
+}
+
+impl SourceFile {
+    /// The path of the file, as written in the `source` statement that loaded it (resolved
+    /// against the directory of the file containing that statement).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+struct Loader<'a> {
+    context: &'a Context,
+    sources: SourceMap,
+    errors: Vec<Error>,
+    /// The canonical paths of the files that are part way through loading, outermost first.
+    stack: Vec<PathBuf>,
+}
+
+impl Loader<'_> {
+    /// Parses a file and loads the files that it sources, returning its statements.
+    fn load(&mut self, path: PathBuf, canonical: PathBuf, text: String) -> Program {
+        let file = self.sources.add(path.clone(), text);
+        let text = &self.sources.files[file.0].text;
+
+        let eof = diagnostic::eof_span(text, file);
+        let parsed = parse_file(text, file);
+
+        let errors: Vec<Error> = parsed
+            .errors
+            .into_iter()
+            .map(|err| match err {
+                Error::Parse(err) => Error::Parse(err.with_eof_span(eof)),
+                err => err,
+            })
+            .collect();
+        let mut errors = errors.into_iter().peekable();
+
+        self.stack.push(canonical);
+
+        let program = self.expand(&path, parsed.program, &mut errors);
+
+        self.errors.extend(errors);
+        self.stack.pop();
+
+        program
+    }
+
+    /// Replaces each `source` statement in `program`, which was parsed from the file at `path`,
+    /// with the statements of the file it names.
+    ///
+    /// Only the branch of an `if` block that applies in the context is expanded, in place, so that
+    /// a file can be sourced on the machines that have it. The other branch is kept as written,
+    /// since its files are only meant to exist where it applies.
+    fn expand(
+        &mut self,
+        path: &Path,
+        program: Program,
+        errors: &mut Peekable<vec::IntoIter<Error>>,
+    ) -> Program {
+        let mut expanded = vec![];
+
+        for statement in program {
+            // Keep the errors in reading order, so that an error in this file comes after the
+            // errors of any file sourced before it.
+            while let Some(err) = errors.next_if(|err| err.offset() < statement.span().start.offset)
+            {
+                self.errors.push(err);
+            }
+
+            match statement {
+                Statement::Source(source) => expanded.extend(self.source(path, &source)),
+                Statement::If(mut if_block) => {
+                    if self.context.test(if_block.condition()) {
+                        if_block.body = self.expand(path, if_block.body, errors);
+                    } else {
+                        if_block.else_body = if_block
+                            .else_body
+                            .map(|else_body| self.expand(path, else_body, errors));
+                    }
+
+                    expanded.push(Statement::If(if_block));
+                }
+                statement => expanded.push(statement),
+            }
+        }
+
+        expanded
+    }
+
+    /// Loads the file named by a `source` statement in the file at `from`, returning its
+    /// statements.
+    fn source(&mut self, from: &Path, source: &Source) -> Program {
+        let mut errors = vec![];
+        let relative = expand(
+            source.path(),
+            &|name| self.context.env.get(name).cloned(),
+            &mut errors,
+        );
+
+        if !errors.is_empty() {
+            self.errors
+                .extend(errors.into_iter().map(Error::Validation));
+
+            return vec![];
+        }
+
+        let path = from
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(self.context.expand_home(&relative));
+
+        let unreadable = |err: io::Error| {
+            SourceError::new(
+                source.path_span(),
+                SourceErrorKind::Unreadable {
+                    path: relative.clone(),
+                    reason: err.to_string(),
+                },
+            )
+        };
+
+        let result = fs::canonicalize(&path)
+            .map_err(unreadable)
+            .and_then(|canonical| {
+                if self.stack.contains(&canonical) {
+                    Err(SourceError::new(
+                        source.path_span(),
+                        SourceErrorKind::Cycle(relative.clone()),
+                    ))
+                } else if self.stack.len() >= MAX_SOURCE_DEPTH {
+                    Err(SourceError::new(
+                        source.path_span(),
+                        SourceErrorKind::TooDeep(MAX_SOURCE_DEPTH),
+                    ))
+                } else {
+                    let text = fs::read_to_string(&path).map_err(unreadable)?;
+
+                    Ok((canonical, text))
+                }
+            });
+
+        match result {
+            Ok((canonical, text)) => self.load(path, canonical, text),
+            Err(err) => {
+                self.errors.push(Error::Source(err));
+
+                vec![]
+            }
+        }
+    }
+}
+
+/// A `source` statement whose file couldn't be loaded.
+#[derive(Debug)]
+pub struct SourceError {
+    span: Span,
+    kind: SourceErrorKind,
+}
+
+#[derive(Debug)]
+pub enum SourceErrorKind {
+    /// The file doesn't exist, or couldn't be read.
+    Unreadable { path: String, reason: String },
+    /// The file is already being loaded, so sourcing it again would never finish.
+    Cycle(String),
+    /// The file would be nested more deeply than the limit allows.
+    TooDeep(usize),
+}
+
+impl SourceError {
+    pub(crate) fn new(span: Span, kind: SourceErrorKind) -> Self {
+        Self { span, kind }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn kind(&self) -> &SourceErrorKind {
+        &self.kind
+    }
+}
+
+impl Diagnostic for SourceError {
+    fn position(&self) -> Position {
+        Position::Span(self.span)
+    }
+
+    fn message(&self) -> String {
+        self.kind.to_string()
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.start.line, self.span.start.col, self.kind
+        )
+    }
+}
+
+impl StdError for SourceError {}
+
+impl fmt::Display for SourceErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceErrorKind::Unreadable { path, reason } => {
+                write!(f, "couldn't read `{}`: {}", path, reason)
+            }
+            SourceErrorKind::Cycle(path) => {
+                write!(
+                    f,
+                    "`{}` is already being loaded, so sourcing it again would never finish",
+                    path
+                )
+            }
+            SourceErrorKind::TooDeep(limit) => {
+                write!(
+                    f,
+                    "`source` statements can't be nested more than {} deep",
+                    limit
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// A directory of config files for a test, which is removed when it's dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = env::temp_dir().join(format!("rolf-parser-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+
+            for (path, text) in files {
+                let path = dir.join(path);
+
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, text).unwrap();
+            }
+
+            Self(dir)
+        }
+
+        fn load(&self, path: &str) -> Loaded {
+            load_config(self.0.join(path)).unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// The commands bound by the `map` statements in `program`, in order.
+    fn commands(program: &Program) -> Vec<&str> {
+        program
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Map(map) => Some(map.command().name()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Each error's message, along with the name of the file it's in and the text it points at.
+    fn errors(loaded: &Loaded) -> Vec<(String, String, &str)> {
+        loaded
+            .errors
+            .iter()
+            .map(|err| {
+                let file = loaded.sources.get(err.file()).unwrap();
+                let text = match err.position() {
+                    Position::Span(span) => span.text(file.text()),
+                    Position::EOF => "",
+                };
+
+                (
+                    err.message(),
+                    file.path()
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                    text,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sources_files_relative_to_the_file_that_sources_them() {
+        let dir = TempDir::new(
+            "relative",
+            &[
+                ("rolfrc", "map j down\nsource keys/g.rolfrc\nmap k up\n"),
+                ("keys/g.rolfrc", "map g g top\nsource \"more.rolfrc\"\n"),
+                ("keys/more.rolfrc", "map G bottom\n"),
+            ],
+        );
+        let loaded = dir.load("rolfrc");
+
+        assert!(loaded.errors.is_empty(), "{:?}", loaded.errors);
+        assert_eq!(commands(&loaded.program), ["down", "top", "bottom", "up"]);
+        assert_eq!(loaded.sources.iter().count(), 3);
+    }
+
+    #[test]
+    fn errors_point_at_the_file_they_are_in() {
+        let dir = TempDir::new(
+            "errors",
+            &[
+                ("rolfrc", "source a.rolfrc\nmap\nsource missing.rolfrc\n"),
+                ("a.rolfrc", "map j down\nbogus\n"),
+            ],
+        );
+        let loaded = dir.load("rolfrc");
+        let errors = errors(&loaded);
+
+        assert_eq!(commands(&loaded.program), ["down"]);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!((errors[0].1.as_str(), errors[0].2), ("a.rolfrc", "bogus"));
+        assert_eq!((errors[1].1.as_str(), errors[1].2), ("rolfrc", "\n"));
+        assert!(
+            errors[2].0.starts_with("couldn't read `missing.rolfrc`: "),
+            "{}",
+            errors[2].0
+        );
+        assert_eq!(
+            (errors[2].1.as_str(), errors[2].2),
+            ("rolfrc", "missing.rolfrc")
+        );
+
+        let report = loaded.sources.render(&loaded.errors[0]);
+        assert!(report.contains("a.rolfrc:2:1"), "{}", report);
+        assert!(report.contains("2 | bogus"), "{}", report);
+    }
+
+    #[test]
+    fn reports_cycles_at_the_source_that_closes_them() {
+        let dir = TempDir::new(
+            "cycle",
+            &[
+                ("rolfrc", "source a.rolfrc\n"),
+                ("a.rolfrc", "map j down\nsource b.rolfrc\n"),
+                ("b.rolfrc", "map k up\nsource a.rolfrc\n"),
+            ],
+        );
+        let loaded = dir.load("rolfrc");
+
+        assert_eq!(commands(&loaded.program), ["down", "up"]);
+        assert_eq!(
+            errors(&loaded),
+            [(
+                "`a.rolfrc` is already being loaded, so sourcing it again would never finish"
+                    .to_string(),
+                "b.rolfrc".to_string(),
+                "a.rolfrc"
+            )]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_depth_limit() {
+        let files: Vec<(String, String)> = (0..=MAX_SOURCE_DEPTH)
+            .map(|index| {
+                (
+                    format!("{}.rolfrc", index),
+                    format!("map j down\nsource {}.rolfrc\n", index + 1),
+                )
+            })
+            .collect();
+        let files: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(path, text)| (path.as_str(), text.as_str()))
+            .collect();
+
+        let dir = TempDir::new("depth", &files);
+        let loaded = dir.load("0.rolfrc");
+
+        assert_eq!(commands(&loaded.program).len(), MAX_SOURCE_DEPTH);
+        assert_eq!(
+            errors(&loaded),
+            [(
+                format!(
+                    "`source` statements can't be nested more than {} deep",
+                    MAX_SOURCE_DEPTH
+                ),
+                format!("{}.rolfrc", MAX_SOURCE_DEPTH - 1),
+                format!("{}.rolfrc", MAX_SOURCE_DEPTH).as_str()
+            )]
+        );
+    }
+
+    #[test]
+    fn only_sources_files_in_the_branch_that_applies() {
+        let dir = TempDir::new(
+            "branches",
+            &[
+                (
+                    "rolfrc",
+                    "if exists ~/.nope.rolfrc { source ~/.nope.rolfrc }\n\
+                     if os macos { source \"mac.rolfrc\" } else { source linux.rolfrc }\n\
+                     if exists ~/local.rolfrc {\n    source ~/local.rolfrc\n}\n",
+                ),
+                ("linux.rolfrc", "map j down\n"),
+                ("local.rolfrc", "map k up\n"),
+            ],
+        );
+
+        let context = Context {
+            os: "linux".to_string(),
+            env: [("HOME".to_string(), dir.0.display().to_string())].into(),
+            ..Context::new()
+        };
+        let loaded = load_config_in(dir.0.join("rolfrc"), &context).unwrap();
+
+        assert!(loaded.errors.is_empty(), "{:?}", loaded.errors);
+
+        let (program, errors) = context.evaluate(&loaded.program);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(commands(&program), ["down", "up"]);
+    }
+}
@@ -0,0 +1,80 @@
+/// A point in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    /// Byte offset from the start of the source.
+    pub offset: usize,
+    /// Line number, counting from 1.
+    pub line: usize,
+    /// Column number in characters, counting from 1.
+    pub col: usize,
+}
+
+impl Location {
+    pub const START: Location = Location {
+        offset: 0,
+        line: 1,
+        col: 1,
+    };
+}
+
+/// Identifies one of the files loaded by [`load_config`](crate::load_config), so that a span can
+/// say which file it came from. Text that is parsed directly, rather than loaded, is always file 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(pub(crate) usize);
+
+impl FileId {
+    /// The index of this file in its [`SourceMap`](crate::SourceMap).
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// The region of source text that a token or AST node was parsed from. `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+    pub file: FileId,
+}
+
+impl Span {
+    pub fn new(start: Location, end: Location) -> Self {
+        Self {
+            start,
+            end,
+            file: FileId::default(),
+        }
+    }
+
+    /// Returns a span that starts where `self` starts and ends where `other` ends.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+
+    /// The number of bytes covered by this span.
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Slices the text covered by this span out of the source it was parsed from.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start.offset..self.end.offset]
+    }
+}
+
+/// Implemented by every token and AST node, so that tools can point back at the source.
+pub trait Spanned {
+    fn span(&self) -> Span;
+
+    /// Slices the text of this node out of the source it was parsed from.
+    fn source_text<'a>(&self, source: &'a str) -> &'a str {
+        self.span().text(source)
+    }
+}
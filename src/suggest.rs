@@ -0,0 +1,84 @@
+//! Suggestions of known names in place of misspelled ones, for error messages.
+
+/// Returns the candidate closest to `name` by edit distance, if any is close enough to be a likely
+/// typo. Ties go to the candidate that comes first.
+pub(crate) fn suggest<'a>(
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Option<&'a str> {
+    // Allow roughly one typo for every three characters, so that short names don't match
+    // everything.
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Damerau-Levenshtein distance between `a` and `b` (optimal string alignment variant), so
+/// that swapping two neighbouring characters, as in `dwon`, counts as a single edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // distances[i][j] is the distance between the first i characters of a and the first j of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_swaps_as_one_edit() {
+        assert_eq!(edit_distance("down", "down"), 0);
+        assert_eq!(edit_distance("dwon", "down"), 1);
+        assert_eq!(edit_distance("entr", "enter"), 1);
+        assert_eq!(edit_distance("", "up"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggests_the_closest_likely_typo() {
+        let names = ["up", "down", "delete", "del"];
+
+        assert_eq!(suggest("dwon", names.into_iter()), Some("down"));
+        assert_eq!(suggest("dlete", names.into_iter()), Some("delete"));
+        assert_eq!(suggest("dle", names.into_iter()), Some("del"));
+        assert_eq!(suggest("top", names.into_iter()), None);
+    }
+
+    #[test]
+    fn short_names_allow_one_edit() {
+        assert_eq!(suggest("x", ["y"].into_iter()), Some("y"));
+        assert_eq!(suggest("xz", ["y"].into_iter()), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! Errors from the passes that check a parsed program against what the host supports.
+
+use core::fmt;
+use std::{error::Error, ops::RangeInclusive};
+
+use crate::{
+    diagnostic::{Diagnostic, Position},
+    options::OptionType,
+    span::Span,
+};
+
+/// A statement that parsed, but doesn't make sense to the host.
+#[derive(Debug)]
+pub struct ValidationError {
+    span: Span,
+    // Boxed because some kinds carry a lot of context, which would make every `Result` that can
+    // hold this error large.
+    kind: Box<ValidationErrorKind>,
+}
+
+#[derive(Debug)]
+pub enum ValidationErrorKind {
+    UnknownOption(String),
+    /// An option was given a value of the wrong type.
+    WrongType {
+        option: String,
+        expected: OptionType,
+        found: String,
+    },
+    /// An integer option was given a value outside of its allowed range.
+    OutOfRange {
+        option: String,
+        value: i64,
+        range: RangeInclusive<i64>,
+    },
+    /// A string option was given a value that isn't one of its allowed values.
+    NotAllowed {
+        option: String,
+        value: String,
+        allowed: Vec<String>,
+    },
+    /// A command that is neither a built-in nor defined by a `cmd` statement, along with a known
+    /// command with a similar name, if there is one.
+    UnknownCommand {
+        name: String,
+        suggestion: Option<String>,
+    },
+    /// A reference to a variable that no `let` statement before it defines, and that isn't set in
+    /// the environment either.
+    UndefinedVariable(String),
+    /// A `${name:?message}` reference to a variable that is unset or empty.
+    MissingVariable {
+        name: String,
+        message: String,
+    },
+}
+
+impl ValidationError {
+    pub(crate) fn new(span: Span, kind: ValidationErrorKind) -> Self {
+        Self {
+            span,
+            kind: Box::new(kind),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn kind(&self) -> &ValidationErrorKind {
+        &self.kind
+    }
+}
+
+impl Diagnostic for ValidationError {
+    fn position(&self) -> Position {
+        Position::Span(self.span)
+    }
+
+    fn message(&self) -> String {
+        self.kind.to_string()
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.start.line, self.span.start.col, self.kind
+        )
+    }
+}
+
+impl Error for ValidationError {}
+
+impl fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationErrorKind::UnknownOption(name) => write!(f, "unknown option `{}`", name),
+            ValidationErrorKind::WrongType {
+                option,
+                expected,
+                found,
+            } => write!(
+                f,
+                "option `{}` expects {}, but was given {}",
+                option, expected, found
+            ),
+            ValidationErrorKind::OutOfRange {
+                option,
+                value,
+                range,
+            } => write!(
+                f,
+                "{} is out of range for option `{}`, which must be from {} to {}",
+                value,
+                option,
+                range.start(),
+                range.end()
+            ),
+            ValidationErrorKind::NotAllowed {
+                option,
+                value,
+                allowed,
+            } => {
+                let allowed: Vec<String> =
+                    allowed.iter().map(|value| format!("`{}`", value)).collect();
+
+                write!(
+                    f,
+                    "`{}` isn't a valid value for option `{}`, which must be one of {}",
+                    value,
+                    option,
+                    allowed.join(", ")
+                )
+            }
+            ValidationErrorKind::UnknownCommand {
+                name,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "unknown command `{}`, did you mean `{}`?",
+                name, suggestion
+            ),
+            ValidationErrorKind::UnknownCommand {
+                name,
+                suggestion: None,
+            } => write!(f, "unknown command `{}`", name),
+            ValidationErrorKind::UndefinedVariable(name) => write!(
+                f,
+                "variable `{}` isn't defined by a `let` statement or set in the environment",
+                name
+            ),
+            ValidationErrorKind::MissingVariable { name, message } if message.is_empty() => {
+                write!(f, "variable `{}` is unset or empty", name)
+            }
+            ValidationErrorKind::MissingVariable { name, message } => {
+                write!(f, "variable `{}` is unset or empty: {}", name, message)
+            }
+        }
+    }
+}